@@ -0,0 +1,307 @@
+use crate::{
+    jpg_optimizer::parse_background,
+    models::{BoundingBox, Scale},
+    resizer::{self, SizeOutput},
+};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+};
+
+/// Size of the source image needed by the largest icon of the bundle
+const SOURCE_SIZE: f64 = 1024.0;
+const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+const APPLE_TOUCH_ICON_SIZE: u32 = 180;
+const PWA_SIZES: [u32; 2] = [192, 512];
+/// Android densities along with the size of the legacy launcher icon.
+/// The adaptive layers are 108dp instead of 48dp.
+const ANDROID_DENSITIES: [(&str, u32); 5] = [
+    ("mdpi", 48),
+    ("hdpi", 72),
+    ("xhdpi", 96),
+    ("xxhdpi", 144),
+    ("xxxhdpi", 192),
+];
+const ADAPTIVE_ICON_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<adaptive-icon xmlns:android="http://schemas.android.com/apk/res/android">
+    <background android:drawable="@mipmap/ic_launcher_background" />
+    <foreground android:drawable="@mipmap/ic_launcher_foreground" />
+</adaptive-icon>
+"#;
+
+/// A Figma frame exported as the full set of platform icons, only available in the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AppIcon {
+    /// Name of the frame, without extension
+    pub name: String,
+    /// Folder of the bundle, relative to the download folder, default: the name of the frame
+    pub path: Option<String>,
+    /// Color of the layers that can't be transparent (apple-touch-icon, Android background
+    /// and PWA maskable icon), default: #ffffff
+    pub background: Option<String>,
+}
+
+impl AppIcon {
+    pub fn folder(&self, download_path: &Path) -> PathBuf {
+        download_path.join(self.path.as_deref().unwrap_or(&self.name))
+    }
+}
+
+/// Lowest scale that renders the frame at the size needed by the largest icon
+pub fn download_scale(bounding_box: Option<BoundingBox>) -> Scale {
+    let side = bounding_box.map_or(0.0, |b| b.width.min(b.height));
+    let scale = if side > 0.0 {
+        // rounded up to hundredths, the precision of the folder names
+        #[allow(clippy::cast_possible_truncation)]
+        let scale = ((SOURCE_SIZE / side * 100.0).ceil() / 100.0) as f32;
+        scale.clamp(Scale::MIN, Scale::MAX)
+    } else {
+        Scale::MAX
+    };
+    Scale::new(scale).unwrap_or(Scale::ONE)
+}
+
+/// Paths of the files of the bundle relative to its folder, without generating them
+pub fn bundle_files() -> Vec<String> {
+    let mut files = vec![
+        "favicon.ico".to_string(),
+        "favicon-16x16.png".to_string(),
+        "favicon-32x32.png".to_string(),
+        "apple-touch-icon.png".to_string(),
+    ];
+    for (density, _) in ANDROID_DENSITIES.iter() {
+        for layer in &[
+            "ic_launcher_foreground",
+            "ic_launcher_background",
+            "ic_launcher",
+        ] {
+            files.push(format!("android/mipmap-{}/{}.png", density, layer));
+        }
+    }
+    files.push("android/mipmap-anydpi-v26/ic_launcher.xml".to_string());
+    for size in PWA_SIZES.iter() {
+        files.push(format!("icon-{}.png", size));
+        files.push(format!("icon-maskable-{}.png", size));
+    }
+    files.push("manifest.json".to_string());
+    files
+}
+
+/// Writes every icon of the bundle to the folder and returns the paths of the written files
+pub fn generate(source: &[u8], icon: &AppIcon, folder: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let background = parse_background(icon.background.as_deref().unwrap_or("#ffffff"))?;
+    let background = Rgba([background[0], background[1], background[2], 255]);
+    let img = image::load_from_memory(source)?;
+    let mut files = vec![];
+
+    let mut write = |relative: &str, bytes: &[u8]| -> anyhow::Result<()> {
+        let path = folder.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, bytes)?;
+        files.push(path);
+        Ok(())
+    };
+
+    // favicons
+    let favicons = FAVICON_SIZES
+        .iter()
+        .map(|size| encode_png(&icon_at(&img, *size, 0)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    write("favicon.ico", &encode_ico(&FAVICON_SIZES, &favicons))?;
+    write("favicon-16x16.png", &favicons[0])?;
+    write("favicon-32x32.png", &favicons[1])?;
+
+    // iOS doesn't support transparency
+    let apple = flatten(&icon_at(&img, APPLE_TOUCH_ICON_SIZE, 0), background);
+    write("apple-touch-icon.png", &encode_png(&apple)?)?;
+
+    // Android adaptive icons keep the important content in the inner 66dp of 108dp
+    for (density, legacy_size) in ANDROID_DENSITIES.iter() {
+        let layer_size = legacy_size * 108 / 48;
+        let folder = format!("android/mipmap-{}", density);
+        let foreground = icon_at(&img, layer_size, layer_size * 21 / 108);
+        let background = RgbaImage::from_pixel(layer_size, layer_size, background);
+        write(
+            &format!("{}/ic_launcher_foreground.png", folder),
+            &encode_png(&foreground)?,
+        )?;
+        write(
+            &format!("{}/ic_launcher_background.png", folder),
+            &encode_png(&background)?,
+        )?;
+        write(
+            &format!("{}/ic_launcher.png", folder),
+            &encode_png(&icon_at(&img, *legacy_size, 0))?,
+        )?;
+    }
+    write(
+        "android/mipmap-anydpi-v26/ic_launcher.xml",
+        ADAPTIVE_ICON_XML.as_bytes(),
+    )?;
+
+    // PWA, maskable icons keep the important content in a circle of 80% of the size
+    let mut manifest_icons = vec![];
+    for size in PWA_SIZES.iter() {
+        let name = format!("icon-{}.png", size);
+        write(&name, &encode_png(&icon_at(&img, *size, 0))?)?;
+        manifest_icons.push(ManifestIcon::new(name, *size, "any"));
+
+        let name = format!("icon-maskable-{}.png", size);
+        let maskable = flatten(&icon_at(&img, *size, size / 10), background);
+        write(&name, &encode_png(&maskable)?)?;
+        manifest_icons.push(ManifestIcon::new(name, *size, "maskable"));
+    }
+    let manifest = serde_json::to_vec_pretty(&serde_json::json!({ "icons": manifest_icons }))?;
+    write("manifest.json", &manifest)?;
+
+    Ok(files)
+}
+
+#[derive(serde::Serialize)]
+struct ManifestIcon {
+    src: String,
+    sizes: String,
+    #[serde(rename = "type")]
+    mime_type: &'static str,
+    purpose: &'static str,
+}
+
+impl ManifestIcon {
+    fn new(src: String, size: u32, purpose: &'static str) -> Self {
+        Self {
+            src,
+            sizes: format!("{}x{}", size, size),
+            mime_type: "image/png",
+            purpose,
+        }
+    }
+}
+
+fn icon_at(img: &DynamicImage, size: u32, padding: u32) -> RgbaImage {
+    resizer::resize(img.clone(), &SizeOutput::square(size, padding))
+}
+
+fn flatten(img: &RgbaImage, background: Rgba<u8>) -> RgbaImage {
+    let mut flattened = RgbaImage::from_pixel(img.width(), img.height(), background);
+    imageops::overlay(&mut flattened, img, 0, 0);
+    flattened
+}
+
+fn encode_png(img: &RgbaImage) -> anyhow::Result<Vec<u8>> {
+    let mut encoded = vec![];
+    image::png::PngEncoder::new(&mut encoded).encode(
+        img.as_raw(),
+        img.width(),
+        img.height(),
+        image::ColorType::Rgba8,
+    )?;
+    Ok(encoded)
+}
+
+/// Multi-resolution ico file embedding the png images, supported by every modern browser
+fn encode_ico(sizes: &[u32], pngs: &[Vec<u8>]) -> Vec<u8> {
+    const HEADER_SIZE: usize = 6;
+    const ENTRY_SIZE: usize = 16;
+    let count = pngs.len();
+    let mut ico = Vec::with_capacity(
+        HEADER_SIZE + ENTRY_SIZE * count + pngs.iter().map(Vec::len).sum::<usize>(),
+    );
+    // reserved, type (1 = icon), number of images
+    ico.extend_from_slice(&[0, 0, 1, 0]);
+    ico.extend_from_slice(&u16::try_from(count).unwrap_or(u16::MAX).to_le_bytes());
+
+    let mut offset = HEADER_SIZE + ENTRY_SIZE * count;
+    for (size, png) in sizes.iter().zip(pngs) {
+        // 0 means 256 pixels
+        let dimension = u8::try_from(*size).unwrap_or(0);
+        // width, height, palette colors, reserved, color planes, bits per pixel
+        ico.extend_from_slice(&[dimension, dimension, 0, 0, 1, 0, 32, 0]);
+        ico.extend_from_slice(&u32::try_from(png.len()).unwrap_or(u32::MAX).to_le_bytes());
+        ico.extend_from_slice(&u32::try_from(offset).unwrap_or(u32::MAX).to_le_bytes());
+        offset += png.len();
+    }
+    for png in pngs {
+        ico.extend_from_slice(png);
+    }
+    ico
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> Vec<u8> {
+        let img = RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        encode_png(&img).unwrap()
+    }
+
+    #[test]
+    fn download_scale_should_reach_the_source_size() {
+        let scale = |width, height| download_scale(Some(BoundingBox { width, height })).value();
+        assert_eq!(scale(1024.0, 1024.0), 1.0);
+        assert_eq!(scale(2048.0, 4096.0), 0.5);
+        assert_eq!(scale(300.0, 300.0), 3.42);
+        assert_eq!(scale(24.0, 24.0), 4.0);
+        assert_eq!(download_scale(None).value(), 4.0);
+    }
+
+    #[test]
+    fn ico_should_contain_every_resolution() -> anyhow::Result<()> {
+        let pngs = FAVICON_SIZES
+            .iter()
+            .map(|s| encode_png(&RgbaImage::new(*s, *s)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let ico = encode_ico(&FAVICON_SIZES, &pngs);
+
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 3, 0]);
+        assert_eq!(ico[6], 16);
+        assert_eq!(ico[6 + 16], 32);
+        assert_eq!(ico[6 + 32], 48);
+        let first_offset = u32::from_le_bytes([ico[18], ico[19], ico[20], ico[21]]) as usize;
+        assert_eq!(
+            &ico[first_offset..first_offset + pngs[0].len()],
+            &pngs[0][..]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bundle_should_contain_every_platform_icon() -> anyhow::Result<()> {
+        let folder = crate::test_utils::temp_folder("fad_app_icon_test");
+        let icon = AppIcon {
+            name: "app_icon".to_string(),
+            path: None,
+            background: Some("#000000".to_string()),
+        };
+        let files = generate(&source(), &icon, &folder)?;
+
+        for expected in &[
+            "favicon.ico",
+            "apple-touch-icon.png",
+            "android/mipmap-xxxhdpi/ic_launcher_foreground.png",
+            "android/mipmap-anydpi-v26/ic_launcher.xml",
+            "icon-maskable-512.png",
+            "manifest.json",
+        ] {
+            assert!(files.contains(&folder.join(expected)), "{}", expected);
+        }
+        let listed = bundle_files()
+            .iter()
+            .map(|f| folder.join(f))
+            .collect::<Vec<_>>();
+        assert_eq!(files, listed);
+        let foreground =
+            image::open(folder.join("android/mipmap-xxxhdpi/ic_launcher_foreground.png"))?;
+        assert_eq!(foreground.to_rgba8().dimensions(), (432, 432));
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(folder.join("manifest.json"))?)?;
+        assert_eq!(manifest["icons"].as_array().map(Vec::len), Some(4));
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,613 @@
+use crate::{
+    models::{Image, Scale},
+    naming::{capitalize, split_words, transliterate},
+    path_template::PathTemplate,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+const HEADER: &str = "Generated by figma-asset-downloader. Do not edit.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    TypeScript,
+    Dart,
+    Swift,
+    Kotlin,
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rust" | "rs" => Ok(Self::Rust),
+            "typescript" | "ts" => Ok(Self::TypeScript),
+            "dart" => Ok(Self::Dart),
+            "swift" => Ok(Self::Swift),
+            "kotlin" | "kt" => Ok(Self::Kotlin),
+            _ => Err(anyhow::anyhow!(
+                "Unknown codegen language {}. Use rust, typescript, dart, swift or kotlin",
+                s
+            )),
+        }
+    }
+}
+
+impl Language {
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::Rust => "assets.rs",
+            Self::TypeScript => "assets.ts",
+            Self::Dart => "assets.dart",
+            Self::Swift => "Assets.swift",
+            Self::Kotlin => "Assets.kt",
+        }
+    }
+
+    /// Words that can't be used as identifiers, including the ones used by the generated code
+    const fn reserved_words(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &["ALL"],
+            Self::TypeScript => &[
+                "allAssets",
+                "break",
+                "case",
+                "catch",
+                "class",
+                "const",
+                "continue",
+                "debugger",
+                "default",
+                "delete",
+                "do",
+                "else",
+                "enum",
+                "export",
+                "extends",
+                "false",
+                "finally",
+                "for",
+                "function",
+                "if",
+                "implements",
+                "import",
+                "in",
+                "instanceof",
+                "interface",
+                "let",
+                "new",
+                "null",
+                "package",
+                "private",
+                "protected",
+                "public",
+                "return",
+                "static",
+                "super",
+                "switch",
+                "this",
+                "throw",
+                "true",
+                "try",
+                "typeof",
+                "var",
+                "void",
+                "while",
+                "with",
+                "yield",
+            ],
+            Self::Dart => &[
+                "all", "assert", "break", "case", "catch", "class", "const", "continue", "default",
+                "do", "else", "enum", "extends", "false", "final", "finally", "for", "if", "in",
+                "is", "new", "null", "rethrow", "return", "super", "switch", "this", "throw",
+                "true", "try", "var", "void", "while", "with",
+            ],
+            Self::Swift => &[
+                "all",
+                "as",
+                "break",
+                "case",
+                "catch",
+                "class",
+                "continue",
+                "default",
+                "defer",
+                "do",
+                "else",
+                "enum",
+                "extension",
+                "fallthrough",
+                "false",
+                "for",
+                "func",
+                "guard",
+                "if",
+                "import",
+                "in",
+                "init",
+                "inout",
+                "internal",
+                "is",
+                "let",
+                "nil",
+                "operator",
+                "private",
+                "protocol",
+                "public",
+                "repeat",
+                "return",
+                "self",
+                "static",
+                "struct",
+                "subscript",
+                "super",
+                "switch",
+                "throw",
+                "throws",
+                "true",
+                "try",
+                "typealias",
+                "var",
+                "where",
+                "while",
+            ],
+            Self::Kotlin => &[
+                "all",
+                "as",
+                "break",
+                "class",
+                "continue",
+                "do",
+                "else",
+                "false",
+                "for",
+                "fun",
+                "if",
+                "in",
+                "interface",
+                "is",
+                "null",
+                "object",
+                "package",
+                "return",
+                "super",
+                "this",
+                "throw",
+                "true",
+                "try",
+                "typealias",
+                "typeof",
+                "val",
+                "var",
+                "when",
+                "while",
+            ],
+        }
+    }
+
+    /// Builds a valid identifier for the language out of an asset name.
+    /// Identifiers are ascii, as some languages don't support other characters.
+    fn identifier(self, name: &str) -> String {
+        let ascii_name = name
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii() => c.to_string(),
+                c => transliterate(c).unwrap_or(" ").to_string(),
+            })
+            .collect::<String>();
+        let mut words = split_words(&ascii_name);
+        if words
+            .first()
+            .is_none_or(|w| w.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            words.insert(0, "asset".to_string());
+        }
+        let identifier = match self {
+            Self::Rust => words
+                .iter()
+                .map(|w| w.to_uppercase())
+                .collect::<Vec<_>>()
+                .join("_"),
+            _ => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect(),
+        };
+        if self.reserved_words().contains(&identifier.as_str()) {
+            format!("{}_", identifier)
+        } else {
+            identifier
+        }
+    }
+
+    /// Builds a string literal escaping the characters with special meaning for the language
+    fn literal(self, value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len() + 2);
+        escaped.push('"');
+        for c in value.chars() {
+            match c {
+                '"' | '\\' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                '$' if matches!(self, Self::Dart | Self::Kotlin) => {
+                    escaped.push_str("\\$");
+                }
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                // control characters are always below \u{ffff}
+                c if c.is_control() => match self {
+                    Self::Rust | Self::Swift => {
+                        escaped.push_str(&format!("\\u{{{:x}}}", c as u32));
+                    }
+                    Self::TypeScript | Self::Dart | Self::Kotlin => {
+                        escaped.push_str(&format!("\\u{:04x}", c as u32));
+                    }
+                },
+                _ => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    fn render(self, assets: &[AssetReference]) -> String {
+        let identifiers = unique_identifiers(self, assets);
+        let entries = assets.iter().zip(identifiers.iter());
+        let mut out = String::new();
+        let list = |values: &[String]| {
+            values
+                .iter()
+                .map(|v| self.literal(v))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let scales = |values: &[Scale]| {
+            values
+                .iter()
+                .map(|s| format!("{:?}", s.value()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        // writing to a String never fails
+        match self {
+            Self::Rust => {
+                let _ = writeln!(out, "// {}\n", HEADER);
+                out.push_str(
+                    "pub struct Asset {\n    pub name: &'static str,\n    pub node_id: &'static str,\n    pub scales: &'static [f32],\n    pub formats: &'static [&'static str],\n    pub paths: &'static [&'static str],\n}\n",
+                );
+                for (asset, id) in entries {
+                    let _ = write!(
+                        out,
+                        "\npub const {}: Asset = Asset {{\n    name: {},\n    node_id: {},\n    scales: &[{}],\n    formats: &[{}],\n    paths: &[{}],\n}};\n",
+                        id,
+                        self.literal(&asset.name),
+                        self.literal(&asset.node_id),
+                        scales(&asset.scales),
+                        list(&asset.formats),
+                        list(&asset.paths),
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "\npub const ALL: &[Asset] = &[{}];",
+                    identifiers.join(", ")
+                );
+            }
+            Self::TypeScript => {
+                let _ = writeln!(out, "// {}\n", HEADER);
+                out.push_str(
+                    "export interface Asset {\n  readonly name: string;\n  readonly nodeId: string;\n  readonly scales: readonly number[];\n  readonly formats: readonly string[];\n  readonly paths: readonly string[];\n}\n",
+                );
+                for (asset, id) in entries {
+                    let _ = write!(
+                        out,
+                        "\nexport const {}: Asset = {{\n  name: {},\n  nodeId: {},\n  scales: [{}],\n  formats: [{}],\n  paths: [{}],\n}};\n",
+                        id,
+                        self.literal(&asset.name),
+                        self.literal(&asset.node_id),
+                        scales(&asset.scales),
+                        list(&asset.formats),
+                        list(&asset.paths),
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "\nexport const allAssets: readonly Asset[] = [{}];",
+                    identifiers.join(", ")
+                );
+            }
+            Self::Dart => {
+                let _ = writeln!(out, "// {}\n", HEADER);
+                out.push_str(
+                    "class Asset {\n  final String name;\n  final String nodeId;\n  final List<double> scales;\n  final List<String> formats;\n  final List<String> paths;\n\n  const Asset({\n    required this.name,\n    required this.nodeId,\n    required this.scales,\n    required this.formats,\n    required this.paths,\n  });\n}\n\nclass Assets {\n  Assets._();\n",
+                );
+                for (asset, id) in entries {
+                    let _ = write!(
+                        out,
+                        "\n  static const {} = Asset(\n    name: {},\n    nodeId: {},\n    scales: [{}],\n    formats: [{}],\n    paths: [{}],\n  );\n",
+                        id,
+                        self.literal(&asset.name),
+                        self.literal(&asset.node_id),
+                        scales(&asset.scales),
+                        list(&asset.formats),
+                        list(&asset.paths),
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "\n  static const all = <Asset>[{}];\n}}",
+                    identifiers.join(", ")
+                );
+            }
+            Self::Swift => {
+                let _ = writeln!(out, "// {}\n", HEADER);
+                out.push_str(
+                    "public struct Asset {\n    public let name: String\n    public let nodeId: String\n    public let scales: [Double]\n    public let formats: [String]\n    public let paths: [String]\n}\n\npublic enum Assets {\n",
+                );
+                for (asset, id) in entries {
+                    let _ = write!(
+                        out,
+                        "\n    public static let {} = Asset(\n        name: {},\n        nodeId: {},\n        scales: [{}],\n        formats: [{}],\n        paths: [{}]\n    )\n",
+                        id,
+                        self.literal(&asset.name),
+                        self.literal(&asset.node_id),
+                        scales(&asset.scales),
+                        list(&asset.formats),
+                        list(&asset.paths),
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "\n    public static let all: [Asset] = [{}]\n}}",
+                    identifiers.join(", ")
+                );
+            }
+            Self::Kotlin => {
+                let _ = writeln!(out, "// {}\n", HEADER);
+                out.push_str(
+                    "data class Asset(\n    val name: String,\n    val nodeId: String,\n    val scales: List<Double>,\n    val formats: List<String>,\n    val paths: List<String>,\n)\n\nobject Assets {\n",
+                );
+                for (asset, id) in entries {
+                    let _ = write!(
+                        out,
+                        "\n    val {} = Asset(\n        name = {},\n        nodeId = {},\n        scales = listOf({}),\n        formats = listOf({}),\n        paths = listOf({}),\n    )\n",
+                        id,
+                        self.literal(&asset.name),
+                        self.literal(&asset.node_id),
+                        scales(&asset.scales),
+                        list(&asset.formats),
+                        list(&asset.paths),
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "\n    val all: List<Asset> = listOf({})\n}}",
+                    identifiers.join(", ")
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Exported asset with all its variants
+#[derive(Debug, PartialEq)]
+pub struct AssetReference {
+    pub name: String,
+    pub node_id: String,
+    pub scales: Vec<Scale>,
+    pub formats: Vec<String>,
+    pub paths: Vec<String>,
+}
+
+/// Groups the images by asset, sorting everything so the generated files are deterministic
+pub fn collect_assets(
+    images: &[Image],
+    base_path: &Path,
+    template: &PathTemplate,
+) -> Vec<AssetReference> {
+    type Variants<'a> = (
+        BTreeSet<Scale>,
+        BTreeSet<&'a str>,
+        BTreeMap<(Scale, &'a str), PathBuf>,
+    );
+    let mut grouped: BTreeMap<(&str, &str), Variants> = BTreeMap::new();
+
+    for image in images {
+        let (scales, formats, paths) = grouped
+            .entry((image.name.trim(), image.id.as_str()))
+            .or_default();
+        scales.insert(image.scale);
+        formats.insert(image.format.as_str());
+        paths.insert(
+            (image.scale, image.format.as_str()),
+            base_path.join(image.relative_path(template)),
+        );
+    }
+
+    grouped
+        .into_iter()
+        .map(|((name, id), (scales, formats, paths))| AssetReference {
+            name: name.to_string(),
+            node_id: id.to_string(),
+            scales: scales.into_iter().collect(),
+            formats: formats.into_iter().map(String::from).collect(),
+            paths: paths.values().map(|p| to_slash_path(p)).collect(),
+        })
+        .collect()
+}
+
+/// Writes one source file per language with the references of all the exported assets
+pub async fn generate(
+    images: &[Image],
+    base_path: &Path,
+    template: &PathTemplate,
+    languages: &[String],
+    output_path: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let languages = languages
+        .iter()
+        .map(|l| l.parse::<Language>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let assets = collect_assets(images, base_path, template);
+
+    tokio::fs::create_dir_all(output_path).await?;
+    let mut written = vec![];
+    for language in languages {
+        let file_path = output_path.join(language.file_name());
+        tokio::fs::write(&file_path, language.render(&assets)).await?;
+        written.push(file_path);
+    }
+    Ok(written)
+}
+
+fn unique_identifiers(language: Language, assets: &[AssetReference]) -> Vec<String> {
+    let mut used = HashSet::new();
+    assets
+        .iter()
+        .map(|asset| {
+            let base = language.identifier(&asset.name);
+            let mut identifier = base.clone();
+            let mut counter = 2;
+            while !used.insert(identifier.clone()) {
+                identifier = format!("{}_{}", base, counter);
+                counter += 1;
+            }
+            identifier
+        })
+        .collect()
+}
+
+fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            Component::ParentDir => Some(".."),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(id: &str, name: &str, scale: f32, format: &str) -> Image {
+        Image::new(
+            id.to_string(),
+            name,
+            Scale::new(scale).unwrap(),
+            format.to_string(),
+            "".to_string(),
+        )
+    }
+
+    #[test]
+    fn language_should_be_parsed_from_aliases() {
+        assert_eq!("ts".parse::<Language>().unwrap(), Language::TypeScript);
+        assert_eq!("Kotlin".parse::<Language>().unwrap(), Language::Kotlin);
+        assert!("cobol".parse::<Language>().is_err());
+    }
+
+    #[test]
+    fn identifiers_should_be_language_safe() {
+        assert_eq!(
+            Language::Rust.identifier("arrow-left icon"),
+            "ARROW_LEFT_ICON"
+        );
+        assert_eq!(
+            Language::Swift.identifier("arrowLeft_icon"),
+            "arrowLeftIcon"
+        );
+        assert_eq!(Language::Kotlin.identifier("24px"), "asset24px");
+        assert_eq!(Language::Dart.identifier("🦀"), "asset");
+        assert_eq!(Language::Dart.identifier("Niño Café"), "ninoCafe");
+        assert_eq!(Language::TypeScript.identifier("default"), "default_");
+    }
+
+    #[test]
+    fn identifiers_should_be_unique() {
+        let assets = collect_assets(
+            &[
+                image("1:1", "icon-a", 1.0, "png"),
+                image("1:2", "icon_a", 1.0, "png"),
+            ],
+            Path::new("downloads"),
+            &PathTemplate::default(),
+        );
+
+        assert_eq!(
+            unique_identifiers(Language::Rust, &assets),
+            vec!["ICON_A", "ICON_A_2"]
+        );
+    }
+
+    #[test]
+    fn assets_should_be_grouped_and_sorted() {
+        let images = [
+            image("1:2", "b", 2.0, "png"),
+            image("1:1", "a", 1.0, "svg"),
+            image("1:2", "b", 1.0, "png"),
+            image("1:2", "b", 1.0, "jpg"),
+        ];
+        let assets = collect_assets(&images, Path::new("downloads"), &PathTemplate::default());
+
+        assert_eq!(
+            assets,
+            vec![
+                AssetReference {
+                    name: "a".to_string(),
+                    node_id: "1:1".to_string(),
+                    scales: vec![Scale::ONE],
+                    formats: vec!["svg".to_string()],
+                    paths: vec!["downloads/a.svg".to_string()],
+                },
+                AssetReference {
+                    name: "b".to_string(),
+                    node_id: "1:2".to_string(),
+                    scales: vec![Scale::ONE, Scale::new(2.0).unwrap()],
+                    formats: vec!["jpg".to_string(), "png".to_string()],
+                    paths: vec![
+                        "downloads/b.jpg".to_string(),
+                        "downloads/b.png".to_string(),
+                        "downloads/2.0x/b.png".to_string(),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn literals_should_be_escaped() {
+        assert_eq!(Language::Kotlin.literal("a$b\"c"), "\"a\\$b\\\"c\"");
+        assert_eq!(Language::Rust.literal("a$b"), "\"a$b\"");
+        assert_eq!(Language::Dart.literal("a\nb\tc"), "\"a\\nb\\tc\"");
+        assert_eq!(Language::Swift.literal("a\u{7}"), "\"a\\u{7}\"");
+        assert_eq!(Language::TypeScript.literal("a\u{7}"), "\"a\\u0007\"");
+    }
+
+    #[test]
+    fn rust_output_should_list_all_assets() {
+        let assets = collect_assets(
+            &[image("1:1", "arrow", 1.0, "png")],
+            Path::new("downloads"),
+            &PathTemplate::default(),
+        );
+        let output = Language::Rust.render(&assets);
+
+        assert!(output.contains("pub const ARROW: Asset = Asset {"));
+        assert!(output.contains("paths: &[\"downloads/arrow.png\"],"));
+        assert!(output.contains("pub const ALL: &[Asset] = &[ARROW];"));
+    }
+}
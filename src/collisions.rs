@@ -0,0 +1,241 @@
+use crate::{models::Image, path_template::PathTemplate};
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+/// Attempts to find free names before giving up
+const MAX_PASSES: usize = 10;
+
+/// What to do when several frames would be written to the same file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionStrategy {
+    /// Reports the collisions and downloads every frame, so the last one wins
+    #[default]
+    Warn,
+    /// Stops before downloading anything
+    Error,
+    /// Appends the node id to the name of every colliding frame but the first one
+    NodeId,
+    /// Appends `_2`, `_3`... to the name of every colliding frame but the first one
+    Counter,
+}
+
+impl FromStr for CollisionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            "node-id" | "node_id" => Ok(Self::NodeId),
+            "counter" => Ok(Self::Counter),
+            _ => Err(anyhow::anyhow!(
+                "Unknown collision strategy {}. Use warn, error, node-id or counter",
+                s
+            )),
+        }
+    }
+}
+
+/// Frames that would be written to the same file, in the order they appear in Figma
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub path: PathBuf,
+    /// Node id and name of every colliding frame
+    pub nodes: Vec<(String, String)>,
+    /// New names of the renamed frames, empty if not resolved
+    pub renamed: Vec<(String, String)>,
+}
+
+impl Collision {
+    pub fn describe(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(id, name)| format!("{} ({})", name, id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut description = format!("{:?} <= {}", self.path, nodes);
+        for (id, name) in &self.renamed {
+            description.push_str(&format!("\n      {} renamed to {}", id, name));
+        }
+        description
+    }
+}
+
+/// Finds the frames whose images would be written to the same file.
+/// Paths are compared ignoring the case, as some file systems are case insensitive.
+pub fn find_collisions(images: &[Image], template: &PathTemplate) -> Vec<Collision> {
+    let mut by_path: BTreeMap<String, (PathBuf, Vec<(String, String)>)> = BTreeMap::new();
+    for image in images {
+        let path = image.relative_path(template);
+        let key = path.to_string_lossy().to_lowercase();
+        let (_, nodes) = by_path.entry(key).or_insert_with(|| (path, vec![]));
+        if !nodes.iter().any(|(id, _)| *id == image.id) {
+            nodes.push((image.id.clone(), image.name.clone()));
+        }
+    }
+
+    let mut collisions: Vec<Collision> = vec![];
+    for (path, nodes) in by_path.into_values().filter(|(_, nodes)| nodes.len() > 1) {
+        // the same frames collide in every scale and format, report them once
+        if !collisions.iter().any(|c| c.nodes == nodes) {
+            collisions.push(Collision {
+                path,
+                nodes,
+                renamed: vec![],
+            });
+        }
+    }
+    collisions
+}
+
+/// Renames the colliding frames following the strategy and returns the collisions found.
+/// Nothing is renamed with `Warn`. Fails if the strategy is `Error` and there are collisions.
+pub fn resolve(
+    images: &mut [Image],
+    template: &PathTemplate,
+    strategy: CollisionStrategy,
+) -> anyhow::Result<Vec<Collision>> {
+    let mut resolved = vec![];
+    for _ in 0..MAX_PASSES {
+        let collisions = find_collisions(images, template);
+        if collisions.is_empty() {
+            return Ok(resolved);
+        }
+        match strategy {
+            CollisionStrategy::Warn => return Ok(collisions),
+            CollisionStrategy::Error => return Err(collision_error(&collisions)),
+            CollisionStrategy::NodeId | CollisionStrategy::Counter => (),
+        }
+
+        let mut renamed_ids: Vec<String> = vec![];
+        for mut collision in collisions {
+            for (position, (id, name)) in collision.nodes.iter().enumerate().skip(1) {
+                if renamed_ids.contains(id) {
+                    continue;
+                }
+                let new_name = match strategy {
+                    CollisionStrategy::NodeId => format!("{}_{}", name, id.replace(':', "-")),
+                    _ => format!("{}_{}", name, position + 1),
+                };
+                for image in images.iter_mut().filter(|i| i.id == *id) {
+                    image.name = new_name.clone();
+                }
+                renamed_ids.push(id.clone());
+                collision.renamed.push((id.clone(), new_name));
+            }
+            resolved.push(collision);
+        }
+    }
+    Err(collision_error(&find_collisions(images, template)))
+}
+
+fn collision_error(collisions: &[Collision]) -> anyhow::Error {
+    let details = collisions
+        .iter()
+        .map(|c| format!("    {}", c.describe()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    anyhow::anyhow!(
+        "Some frames would overwrite each other. Rename them in Figma or use --on-collision node-id or counter:\n{}",
+        details
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Scale;
+
+    fn image(id: &str, name: &str, scale: f32) -> Image {
+        Image::new(
+            id.to_string(),
+            name,
+            Scale::new(scale).unwrap(),
+            "png".to_string(),
+            "".to_string(),
+        )
+    }
+
+    fn images() -> Vec<Image> {
+        vec![
+            image("1:1", "Icon", 1.0),
+            image("1:1", "Icon", 2.0),
+            image("1:2", "icon", 1.0),
+            image("1:2", "icon", 2.0),
+            image("1:3", "arrow", 1.0),
+        ]
+    }
+
+    #[test]
+    fn collisions_should_ignore_the_case() {
+        let collisions = find_collisions(&images(), &PathTemplate::default());
+
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(
+            collisions[0].nodes,
+            vec![
+                ("1:1".to_string(), "Icon".to_string()),
+                ("1:2".to_string(), "icon".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn error_strategy_should_report_the_nodes() {
+        let error = resolve(
+            &mut images(),
+            &PathTemplate::default(),
+            CollisionStrategy::Error,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains("Icon (1:1), icon (1:2)"));
+    }
+
+    #[test]
+    fn warn_strategy_should_keep_the_names() {
+        let mut images = images();
+        let collisions = resolve(
+            &mut images,
+            &PathTemplate::default(),
+            CollisionStrategy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(collisions.len(), 1);
+        assert!(collisions[0].renamed.is_empty());
+        assert_eq!(images[2].name, "icon");
+    }
+
+    #[test]
+    fn colliding_frames_should_be_renamed() {
+        let template = PathTemplate::default();
+        for (strategy, expected) in [
+            (CollisionStrategy::NodeId, "icon_1-2"),
+            (CollisionStrategy::Counter, "icon_2"),
+        ] {
+            let mut images = images();
+            let collisions = resolve(&mut images, &template, strategy).unwrap();
+
+            assert_eq!(collisions.len(), 1);
+            assert_eq!(images[0].name, "Icon");
+            assert_eq!(images[2].name, expected);
+            assert_eq!(images[3].name, expected);
+            assert!(find_collisions(&images, &template).is_empty());
+        }
+    }
+
+    #[test]
+    fn counter_should_skip_existing_names() {
+        let template = PathTemplate::default();
+        let mut images = vec![
+            image("1:1", "icon", 1.0),
+            image("1:2", "icon", 1.0),
+            image("1:3", "icon_2", 1.0),
+        ];
+        resolve(&mut images, &template, CollisionStrategy::Counter).unwrap();
+
+        assert!(find_collisions(&images, &template).is_empty());
+    }
+}
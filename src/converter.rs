@@ -0,0 +1,215 @@
+use crate::{emojis::ERROR, models::Image, path_template::PathTemplate};
+use log::warn;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+
+/// Formats that are not rendered by Figma but derived locally from the downloaded raster images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DerivedFormat {
+    WebP,
+    Avif,
+}
+
+impl FromStr for DerivedFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "webp" => Ok(Self::WebP),
+            "avif" if cfg!(feature = "avif") => Ok(Self::Avif),
+            "avif" => Err(anyhow::anyhow!(
+                "This version of fad has been built without avif support. Install it with `--features avif`"
+            )),
+            _ => Err(anyhow::anyhow!(
+                "Unknown derived format {}. Use webp or avif",
+                s
+            )),
+        }
+    }
+}
+
+impl DerivedFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+        }
+    }
+}
+
+/// Settings of the conversion. A `None` webp quality means lossless.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ConversionOptions {
+    pub webp_quality: Option<u8>,
+    pub avif_quality: Option<u8>,
+}
+
+pub fn parse_formats(formats: &[String]) -> anyhow::Result<Vec<DerivedFormat>> {
+    formats.iter().map(|f| f.parse()).collect()
+}
+
+/// Only raster images can be converted
+pub fn is_convertible(extension: &str) -> bool {
+    matches!(extension, "png" | "jpg" | "jpeg")
+}
+
+/// The image in the derived format, produced locally instead of rendered by Figma
+pub fn derived_image(image: &Image, format: DerivedFormat) -> Image {
+    let mut derived = image.clone();
+    derived.format = format.extension().to_string();
+    derived.url = String::new();
+    derived
+}
+
+/// Derived images with the image and the format they're converted from and to.
+/// A frame exported as png and jpg would produce the same derived file twice,
+/// so it's converted only once, from the png as it's lossless. Different frames producing
+/// the same derived file are reported, only the first one is converted.
+pub fn derived_images<'a>(
+    images: &'a [Image],
+    formats: &[DerivedFormat],
+    template: &PathTemplate,
+) -> Vec<(&'a Image, DerivedFormat, Image)> {
+    let mut sources = images
+        .iter()
+        .filter(|i| is_convertible(&i.format))
+        .collect::<Vec<_>>();
+    // stable, so the order of the rest of the images is kept
+    sources.sort_by_key(|i| i.format != "png");
+
+    let mut sources_by_path: HashMap<PathBuf, &str> = HashMap::new();
+    let mut derived = vec![];
+    for source in sources {
+        for format in formats {
+            let image = derived_image(source, *format);
+            let path = image.relative_path(template);
+            match sources_by_path.get(&path) {
+                Some(id) if *id != source.id => warn!(
+                    "{} {:?} is derived from the frames {} and {}, only the first one is converted",
+                    ERROR, path, id, source.id
+                ),
+                Some(_) => (),
+                None => {
+                    sources_by_path.insert(path, &source.id);
+                    derived.push((source, *format, image));
+                }
+            }
+        }
+    }
+    derived
+}
+
+/// Converts the image into the derived format and writes it to the target path
+pub fn convert_image(
+    source: &Path,
+    target: &Path,
+    format: DerivedFormat,
+    options: ConversionOptions,
+) -> anyhow::Result<()> {
+    let img = image::open(source)?.to_rgba8();
+    let (width, height) = img.dimensions();
+    let bytes = match format {
+        DerivedFormat::WebP => encode_webp(img.as_raw(), width, height, options.webp_quality),
+        DerivedFormat::Avif => encode_avif(
+            img.as_raw(),
+            width,
+            height,
+            options.avif_quality.unwrap_or(DEFAULT_AVIF_QUALITY),
+        )?,
+    };
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(target, bytes)?;
+    Ok(())
+}
+
+fn encode_webp(rgba: &[u8], width: u32, height: u32, quality: Option<u8>) -> Vec<u8> {
+    let encoder = webp::Encoder::from_rgba(rgba, width, height);
+    let memory = match quality {
+        Some(q) => encoder.encode(f32::from(q.min(100))),
+        None => encoder.encode_lossless(),
+    };
+    memory.to_vec()
+}
+
+#[cfg(feature = "avif")]
+fn encode_avif(rgba: &[u8], width: u32, height: u32, quality: u8) -> anyhow::Result<Vec<u8>> {
+    let pixels = rgba
+        .chunks_exact(4)
+        .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect::<Vec<_>>();
+    let img = ravif::Img::new(pixels.as_slice(), width as usize, height as usize);
+    let encoded = ravif::Encoder::new()
+        .with_quality(f32::from(quality.min(100)))
+        .encode_rgba(img)?;
+    Ok(encoded.avif_file)
+}
+
+#[cfg(not(feature = "avif"))]
+fn encode_avif(_: &[u8], _: u32, _: u32, _: u8) -> anyhow::Result<Vec<u8>> {
+    Err(anyhow::anyhow!(
+        "This version of fad has been built without avif support"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_format_should_be_parsed() {
+        assert_eq!(
+            "WebP".parse::<DerivedFormat>().unwrap(),
+            DerivedFormat::WebP
+        );
+        assert_eq!(
+            "avif".parse::<DerivedFormat>().is_ok(),
+            cfg!(feature = "avif")
+        );
+        assert!("gif".parse::<DerivedFormat>().is_err());
+    }
+
+    #[test]
+    fn frames_exported_as_png_and_jpg_should_be_converted_once_from_the_png() {
+        let image = |format: &str| {
+            Image::new(
+                "1:1".to_string(),
+                "hero",
+                crate::models::Scale::ONE,
+                format.to_string(),
+                "".to_string(),
+            )
+        };
+        let images = [image("jpg"), image("png"), image("svg")];
+        let derived = derived_images(
+            &images,
+            &[DerivedFormat::WebP, DerivedFormat::Avif],
+            &PathTemplate::default(),
+        );
+
+        assert_eq!(
+            derived
+                .iter()
+                .map(|(source, _, image)| (source.format.as_str(), image.format.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("png", "webp"), ("png", "avif")]
+        );
+    }
+
+    #[test]
+    fn webp_should_be_encoded_lossless_and_lossy() {
+        let rgba = [255, 0, 0, 255].repeat(16);
+        let lossless = encode_webp(&rgba, 4, 4, None);
+        let lossy = encode_webp(&rgba, 4, 4, Some(75));
+
+        assert_eq!(&lossless[..4], b"RIFF");
+        assert_eq!(&lossless[8..12], b"WEBP");
+        assert_eq!(&lossy[8..12], b"WEBP");
+    }
+}
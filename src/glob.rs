@@ -0,0 +1,46 @@
+/// Minimal glob matching supporting `*` and `?`
+pub fn glob_matches(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let value = value.chars().collect::<Vec<_>>();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_should_match_wildcards() {
+        assert!(glob_matches("illustration*", "illustration_home"));
+        assert!(glob_matches(
+            "*/illustrations/*",
+            "downloads/illustrations/home.png"
+        ));
+        assert!(glob_matches("icon-?", "icon-a"));
+        assert!(!glob_matches("icon-?", "icon-ab"));
+        assert!(!glob_matches("illustration*", "icon"));
+    }
+}
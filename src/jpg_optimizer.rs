@@ -0,0 +1,214 @@
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
+
+const DEFAULT_BACKGROUND: [u8; 3] = [255, 255, 255];
+const SOI: [u8; 2] = [0xFF, 0xD8];
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+const APP2: u8 = 0xE2;
+const SOS: u8 = 0xDA;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+/// Settings of the jpg optimization
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JpgOptions {
+    pub quality: u8,
+    /// Color used to flatten the images with transparency, as jpg has no alpha channel
+    pub background: [u8; 3],
+    /// If true, the icc profile and the exif metadata of the original image are dropped
+    pub strip_metadata: bool,
+}
+
+impl JpgOptions {
+    pub const fn new(quality: u8) -> Self {
+        Self {
+            quality,
+            background: DEFAULT_BACKGROUND,
+            strip_metadata: false,
+        }
+    }
+
+    pub const fn with_background(mut self, background: [u8; 3]) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub const fn with_strip_metadata(mut self, strip_metadata: bool) -> Self {
+        self.strip_metadata = strip_metadata;
+        self
+    }
+}
+
+/// Parses colors like `#ffffff` or `ffffff`
+pub fn parse_background(color: &str) -> anyhow::Result<[u8; 3]> {
+    let hex = color.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(anyhow::anyhow!(
+            "Invalid jpg background {}. Use an hex color like #ffffff",
+            color
+        )),
+    }
+}
+
+/// Re-encodes the image as jpg with the given quality
+pub fn optimize(original: &[u8], options: JpgOptions) -> anyhow::Result<Vec<u8>> {
+    let encoded = encode(image::load_from_memory(original)?, options)?;
+
+    if options.strip_metadata {
+        Ok(encoded)
+    } else {
+        Ok(insert_segments(&encoded, &metadata_segments(original)))
+    }
+}
+
+/// Encodes a decoded image as jpg, flattening its transparency against the background
+pub fn encode(img: DynamicImage, options: JpgOptions) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let mut encoded = vec![];
+    let mut encoder = image::jpeg::JpegEncoder::new_with_quality(&mut encoded, options.quality);
+
+    match flatten(img, options.background) {
+        DynamicImage::ImageLuma8(gray) => {
+            encoder.encode(gray.as_raw(), width, height, image::ColorType::L8)?;
+        }
+        other => {
+            let rgb = other.to_rgb8();
+            encoder.encode(rgb.as_raw(), width, height, image::ColorType::Rgb8)?;
+        }
+    }
+    Ok(encoded)
+}
+
+/// Blends the images with transparency against the background and
+/// converts everything else to the 8 bit color types supported by the encoder
+fn flatten(img: DynamicImage, background: [u8; 3]) -> DynamicImage {
+    if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        let flattened = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let pixel = rgba.get_pixel(x, y);
+            let alpha = u16::from(pixel[3]);
+            let blend = |channel: usize| {
+                let value = u16::from(pixel[channel]) * alpha
+                    + u16::from(background[channel]) * (255 - alpha);
+                // the result is always <= 255
+                #[allow(clippy::cast_possible_truncation)]
+                let value = ((value + 127) / 255) as u8;
+                value
+            };
+            Rgb([blend(0), blend(1), blend(2)])
+        });
+        DynamicImage::ImageRgb8(flattened)
+    } else {
+        match img {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => img,
+            DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma8(img.to_luma8()),
+            _ => DynamicImage::ImageRgb8(img.to_rgb8()),
+        }
+    }
+}
+
+/// Gets the exif and icc profile segments of a jpg. Other images have no segments.
+fn metadata_segments(jpg: &[u8]) -> Vec<&[u8]> {
+    let mut segments = vec![];
+    if !jpg.starts_with(&SOI) {
+        return segments;
+    }
+
+    let mut i = SOI.len();
+    while i + 4 <= jpg.len() && jpg[i] == 0xFF {
+        let marker = jpg[i + 1];
+        if marker == SOS {
+            break;
+        }
+        let length = usize::from(u16::from_be_bytes([jpg[i + 2], jpg[i + 3]]));
+        let end = i + 2 + length;
+        if length < 2 || end > jpg.len() {
+            break;
+        }
+        let payload = &jpg[i + 4..end];
+        if (marker == APP1 && payload.starts_with(EXIF_HEADER))
+            || (marker == APP2 && payload.starts_with(ICC_HEADER))
+        {
+            segments.push(&jpg[i..end]);
+        }
+        i = end;
+    }
+    segments
+}
+
+/// Inserts the segments after the JFIF header of the encoded jpg
+fn insert_segments(jpg: &[u8], segments: &[&[u8]]) -> Vec<u8> {
+    if segments.is_empty() || !jpg.starts_with(&SOI) {
+        return jpg.to_vec();
+    }
+    let mut position = SOI.len();
+    if jpg.len() > position + 4 && jpg[position] == 0xFF && jpg[position + 1] == APP0 {
+        position += 2 + usize::from(u16::from_be_bytes([jpg[position + 2], jpg[position + 3]]));
+    }
+
+    let mut result =
+        Vec::with_capacity(jpg.len() + segments.iter().map(|s| s.len()).sum::<usize>());
+    result.extend_from_slice(&jpg[..position]);
+    for segment in segments {
+        result.extend_from_slice(segment);
+    }
+    result.extend_from_slice(&jpg[position..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGBA_PNG: &[u8] = include_bytes!("../fixtures/rgba.png");
+    const GRAY16_PNG: &[u8] = include_bytes!("../fixtures/gray16.png");
+    const ICC_JPG: &[u8] = include_bytes!("../fixtures/icc.jpg");
+
+    #[test]
+    fn transparent_images_are_flattened_against_the_background() -> anyhow::Result<()> {
+        let options = JpgOptions::new(100).with_background([255, 0, 0]);
+        let jpg = image::load_from_memory(&optimize(RGBA_PNG, options)?)?.to_rgb8();
+
+        // the first pixel is fully transparent, the second one is opaque blue
+        let transparent = jpg.get_pixel(0, 0);
+        let opaque = jpg.get_pixel(1, 0);
+        assert!(transparent[0] > 200 && transparent[1] < 50 && transparent[2] < 50);
+        assert!(opaque[0] < 50 && opaque[1] < 50 && opaque[2] > 200);
+        Ok(())
+    }
+
+    #[test]
+    fn sixteen_bit_images_are_supported() -> anyhow::Result<()> {
+        let jpg = optimize(GRAY16_PNG, JpgOptions::new(80))?;
+
+        assert_eq!(image::load_from_memory(&jpg)?.color(), image::ColorType::L8);
+        Ok(())
+    }
+
+    #[test]
+    fn icc_profiles_are_preserved_unless_stripped() -> anyhow::Result<()> {
+        let original_segments = metadata_segments(ICC_JPG);
+        assert_eq!(original_segments.len(), 1);
+
+        let preserved = optimize(ICC_JPG, JpgOptions::new(80))?;
+        assert_eq!(metadata_segments(&preserved), original_segments);
+        assert!(image::load_from_memory(&preserved).is_ok());
+
+        let stripped = optimize(ICC_JPG, JpgOptions::new(80).with_strip_metadata(true))?;
+        assert!(metadata_segments(&stripped).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn background_should_be_parsed_from_hex() {
+        assert_eq!(parse_background("#ff8000").unwrap(), [255, 128, 0]);
+        assert_eq!(parse_background("000000").unwrap(), [0, 0, 0]);
+        assert!(parse_background("#fff").is_err());
+        assert!(parse_background("#gggggg").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::progress;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    convert::TryFrom,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Whether the messages are written to stderr, so stdout only carries the json report
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// Format of the log messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// The messages as they are, with emojis and colors
+    #[default]
+    Human,
+    /// One json object per line, without emojis and colors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" | "text" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow::anyhow!(
+                "Unknown log format {}. Use human or json",
+                s
+            )),
+        }
+    }
+}
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = if JSON_FORMAT.load(Ordering::Relaxed) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX));
+            json_line(record.level(), &record.args().to_string(), timestamp)
+        } else {
+            record.args().to_string()
+        };
+        // errors and warnings never go to stdout
+        let to_stderr = record.level() <= Level::Warn || TO_STDERR.load(Ordering::Relaxed);
+        progress::print_above(|| {
+            if to_stderr {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Sets the logger up with the default configuration.
+/// Must be called once, before any message is logged.
+pub fn init() -> anyhow::Result<()> {
+    log::set_boxed_logger(Box::new(Logger))
+        .map_err(|e| anyhow::anyhow!("Unable to set the logger up: {}", e))?;
+    log::set_max_level(LevelFilter::Info);
+    Ok(())
+}
+
+/// Sets the level and the format of the messages.
+/// `to_stderr` writes every message to stderr instead of stdout.
+pub fn configure(level: LevelFilter, format: LogFormat, to_stderr: bool) {
+    log::set_max_level(level);
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::Relaxed);
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+    if format == LogFormat::Json {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+pub fn is_json() -> bool {
+    JSON_FORMAT.load(Ordering::Relaxed)
+}
+
+fn json_line(level: Level, message: &str, timestamp: u64) -> String {
+    serde_json::json!({
+        "timestamp_ms": timestamp,
+        "level": level.as_str().to_lowercase(),
+        "message": plain_message(message),
+    })
+    .to_string()
+}
+
+/// Removes the colors and the leading emojis and spaces
+fn plain_message(message: &str) -> String {
+    console::strip_ansi_codes(message)
+        .trim_start_matches(|c: char| !c.is_ascii() || c.is_whitespace())
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_format_should_be_parsed() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("Human".parse::<LogFormat>().unwrap(), LogFormat::Human);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn json_lines_should_not_contain_emojis_or_colors() {
+        let message = format!(
+            "{}  {}",
+            "\u{1F680}",
+            console::style("Preparing...")
+                .bold()
+                .green()
+                .force_styling(true)
+        );
+        let line: serde_json::Value =
+            serde_json::from_str(&json_line(Level::Info, &message, 42)).unwrap();
+
+        assert_eq!(line["message"], "Preparing...");
+        assert_eq!(line["level"], "info");
+        assert_eq!(line["timestamp_ms"], 42);
+    }
+}
@@ -3,192 +3,800 @@ use crate::models::*;
 use console::style;
 use emojis::*;
 use futures::prelude::*;
+use log::{debug, error, info, trace, warn};
 use manifest_checker::{ManifestChecker, ManifestInfo, TokioManifestReader};
+use optimizer::{OptimizationResult, OptimizationSummary, Optimizer};
+use path_template::PathTemplate;
+use report::{FileKind, OutputFormat, RunReport};
 use reqwest::{header, Client};
+use snapshot::Snapshot;
+use std::net::ToSocketAddrs;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
-use tokio::io::AsyncWriteExt;
+use tokio::{io::AsyncWriteExt, sync::Notify};
 
+mod app_icon;
+mod codegen;
+mod collisions;
+mod converter;
 mod emojis;
+mod glob;
+mod jpg_optimizer;
+mod logger;
 mod manifest_checker;
+mod manifest_generator;
 mod models;
+mod naming;
+mod optimizer;
+mod path_template;
+mod planner;
+mod png_quantizer;
+mod progress;
+mod pruner;
+mod report;
+mod resizer;
+mod snapshot;
+mod svg_optimizer;
+#[cfg(test)]
+mod test_utils;
+mod watcher;
+mod webhook;
+
+/// Stages with progress of a run: frames, urls and downloads
+const STAGES: usize = 3;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let start = Instant::now();
+    logger::init()?;
     let cli = get_cli().await?;
+    let output_format = cli.output_format()?;
+    logger::configure(
+        cli.log_level(),
+        cli.log_format()?,
+        output_format == OutputFormat::Json,
+    );
+    let report_path = cli.report.clone();
 
-    if let Some(SubCommands::ValidateManifest { path }) = cli.subcommands {
-        // we have only one subcommand so no need to get more details than this
-        let manifest_path: PathBuf = std::env::current_dir()?.join(&path);
-        let manifest_checker =
-            ManifestChecker::<TokioManifestReader>::with_tokio_reader(&manifest_path);
-        let manifest_result = manifest_checker.check().await;
-        match manifest_result {
-            Ok(manifest) => {
-                manifest.print_info();
-
-                if !cli.opt_only_on_validation {
-                    return Ok(());
-                }
+    let mut report = RunReport::default();
+    let result = run(cli, &mut report).await;
+    if let Err(e) = &result {
+        report.add_error(format!("{:#}", e));
+    }
+    report.finish(start);
 
-                if let ManifestInfo {
-                    new_assets: Some(new_assets),
-                    assets_dir_path: Some(assets_dir_path),
-                    ..
-                } = manifest
-                {
-                    // NOTE: Rayon doesn't work well here.
-                    for asset in new_assets {
-                        let path = assets_dir_path.join(asset);
-                        let extension = path
-                            .extension()
-                            .and_then(std::ffi::OsStr::to_str)
-                            .unwrap_or("png");
-                        if let Err(e) =
-                            optimize_image(&path, extension, cli.opt_png_level, cli.opt_jpg_level)
-                        {
-                            println!("{} Error optimizing image {:?} => {:?}", ERROR, &path, e);
-                        }
-                    }
-                }
+    if let Some(report_path) = report_path {
+        if let Err(e) = report.write_json(Path::new(&report_path)).await {
+            error!(
+                "{} Error writing the report {:?} => {:?}",
+                ERROR, report_path, e
+            );
+        }
+    }
+    print_elapsed(start);
+    if output_format == OutputFormat::Json {
+        // the only output written to stdout
+        println!("{}", report.to_json()?);
+    }
+    result
+}
+
+async fn run(cli: Cli, report: &mut RunReport) -> anyhow::Result<()> {
+    match &cli.subcommands {
+        Some(SubCommands::ValidateManifest { path, remote }) => {
+            validate_manifest(&cli, path, *remote, report).await
+        }
+        Some(SubCommands::Watch { interval, debounce }) => {
+            watch(
+                &cli,
+                Duration::from_secs((*interval).max(1)),
+                Duration::from_secs(*debounce),
+            )
+            .await
+        }
+        Some(SubCommands::Manifest(command)) => manifest(&cli, command).await,
+        Some(SubCommands::Snapshot { path }) => snapshot(&cli, Path::new(path)).await,
+        Some(SubCommands::Versions { limit }) => versions(&cli, *limit).await,
+        Some(SubCommands::Serve {
+            host,
+            port,
+            passcode,
+        }) => serve(&cli, host, *port, passcode).await,
+        None => sync(&cli, report).await,
+    }
+}
+
+async fn validate_manifest(
+    cli: &Cli,
+    path: &str,
+    remote: bool,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    let optimizer = Optimizer::new(cli.optimization_options()?, cli.opt_threads);
+    let naming_policy = cli.naming_policy()?;
+
+    let manifest_path: PathBuf = std::env::current_dir()?.join(path);
+    let manifest_checker =
+        ManifestChecker::<TokioManifestReader>::with_tokio_reader(&manifest_path)
+            .with_naming_policy(naming_policy);
+    let manifest_result = if remote {
+        // the frames of a snapshot are enough, the images are not needed
+        let source = Source::new(cli, true).await?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Some arguments are missing. Check access token, file id or document ids."
+            )
+        })?;
+        let frames = source.frames().await?.unwrap_or_default();
+        manifest_checker
+            .check_remote(&source.file_id, &frames)
+            .await
+    } else {
+        manifest_checker.check().await
+    };
+    match manifest_result {
+        Ok(manifest) => {
+            manifest.print_info();
+
+            // remote new assets are frames, there are no files to optimize
+            if remote || !cli.opt_only_on_validation {
+                return Ok(());
             }
-            Err(e) => {
-                println!(
-                    "{} {}",
-                    ERROR,
-                    style(format!(
-                        "Some error occurred while trying to work with the manifest:\n\n{}",
-                        e
-                    ))
+
+            if let ManifestInfo {
+                new_assets: Some(new_assets),
+                assets_dir_path: Some(assets_dir_path),
+                ..
+            } = manifest
+            {
+                let paths: Vec<_> = new_assets
+                    .into_iter()
+                    .map(|asset| assets_dir_path.join(asset))
+                    .collect();
+                let stage = progress::Stage::start(1, 1, "Optimizing", "files", paths.len());
+                let results = optimizer.optimize_all(paths, &stage).await;
+                stage.finish();
+                report.optimization =
+                    Some(report_optimization(&results, cli.opt_report.as_deref()).await);
+            }
+        }
+        Err(e) => {
+            error!(
+                "{} {}",
+                ERROR,
+                style(format!(
+                    "Some error occurred while trying to work with the manifest:\n\n{}",
+                    e
+                ))
+                .bold()
+                .red(),
+            );
+            report.add_error(e.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the assets of the file and generates everything derived from them
+async fn sync(cli: &Cli, report: &mut RunReport) -> anyhow::Result<()> {
+    let source = match Source::new(cli, cli.dry_run && !cli.resolve_urls).await? {
+        Some(source) => source,
+        None => {
+            error!(
+                "{}  {}",
+                ERROR,
+                style("Some arguments are missing. Check access token, file id or document ids.")
                     .bold()
                     .red(),
+            );
+            report.add_error(
+                "Some arguments are missing. Check access token, file id or document ids.",
+            );
+            return Ok(());
+        }
+    };
+    let optimizer = Optimizer::new(cli.optimization_options()?, cli.opt_threads);
+    let conversion_options = cli.conversion_options();
+    let path_template = cli.path_template()?;
+    let collision_strategy = cli.collision_strategy()?;
+    let naming_policy = cli.naming_policy()?;
+    let prune_mode = cli.prune_mode()?;
+    let size_outputs = cli.size_outputs()?;
+    let trash_path = pruner::trash_path(cli.prune_trash_path.as_deref())?;
+
+    let scales = &cli.file_scales;
+    let formats = &cli.file_extensions;
+    let force_extensions = cli.force_file_extensions;
+    let derived_formats =
+        converter::parse_formats(cli.derived_formats.as_deref().unwrap_or_default())?;
+    let download_path: PathBuf = std::env::current_dir()?.join(&cli.path);
+    let file = source.file();
+    report.file_id = Some(source.file_id.clone());
+    report.file_version = source.file_version.clone();
+    report.document_ids = source.document_ids.clone();
+    report.download_path = Some(download_path.clone());
+
+    let stage = Instant::now();
+    let frames = source.frames().await?;
+    report.frames = frames.as_ref().map_or(0, Vec::len);
+    report.add_stage("frames", stage);
+
+    // app icon frames are exported as bundles, not as images
+    let image_frames = without_app_icons(&frames, cli.app_icons.as_deref().unwrap_or_default());
+
+    let stage = Instant::now();
+    let requested = requested_images(&image_frames, scales, formats, force_extensions);
+    report.images_requested = requested.len();
+    let mut images = if cli.dry_run && !cli.resolve_urls {
+        requested.clone()
+    } else {
+        get_images(
+            &image_frames,
+            source.client()?,
+            file,
+            scales,
+            formats,
+            force_extensions,
+            report,
+        )
+        .await
+    };
+    report.images_resolved = images.len();
+    report.add_stage("urls", stage);
+    for image in &mut images {
+        image.name = naming_policy.apply(&image.name);
+    }
+    let collisions = collisions::resolve(&mut images, &path_template, collision_strategy)?;
+    report_collisions(&collisions);
+
+    if cli.dry_run {
+        let mut plan = planner::Plan::new(&download_path);
+        // images that Figma didn't render
+        let skipped = requested
+            .into_iter()
+            .filter(|r| {
+                !images
+                    .iter()
+                    .any(|i| i.id == r.id && i.scale == r.scale && i.format == r.format)
+            })
+            .map(|mut r| {
+                r.name = naming_policy.apply(&r.name);
+                r
+            })
+            .collect::<Vec<_>>();
+        plan_images(
+            &mut plan,
+            &images,
+            &skipped,
+            &path_template,
+            &derived_formats,
+            size_outputs,
+            cli.download_only_unexisting_in_folder,
+        );
+        if let Some(icons) = &cli.app_icons {
+            plan_app_icons(&mut plan, &frames, icons);
+        }
+        // without images we can't know which files are stale
+        if !images.is_empty() {
+            plan_pruning(&mut plan, checked_prune_mode(prune_mode, &images, report));
+        }
+        plan.print();
+        if let Some(report_path) = &cli.plan_report {
+            if let Err(e) = plan.write_json(Path::new(report_path)).await {
+                error!(
+                    "{} Error writing the plan {:?} => {:?}",
+                    ERROR, report_path, e
                 );
             }
         }
-    } else if let (Some(token), Some(file_id), Some(document_ids)) =
-        (cli.personal_access_token, cli.file_id, cli.document_ids)
-    {
-        let scales = cli.file_scales;
-        let formats = cli.file_extensions;
-        let force_extensions = cli.force_file_extensions;
-        let download_path: PathBuf = std::env::current_dir()?.join(&cli.path);
-
-        let client = get_client(&token)?;
-        let frames = get_frames(&file_id, &document_ids, &client).await?;
-        let images = get_images(
-            &frames,
-            &client,
-            &file_id,
-            &scales,
-            &formats,
-            force_extensions,
+        return Ok(());
+    }
+
+    let client = source.client()?;
+    let mut exported_images = vec![];
+
+    if images.is_empty() {
+        info!(
+            "{}  {}",
+            INFO,
+            style("No images found to download").bold().blue()
+        );
+    } else {
+        info!(
+            "{}  {}",
+            FOLDER,
+            style("Creating the folder structure...").bold().green()
+        );
+        tokio::fs::create_dir_all(download_path.clone()).await?;
+
+        let images_to_process = get_images_info_to_process(
+            &images,
+            &download_path,
+            &path_template,
+            cli.download_only_unexisting_in_folder,
+        )
+        .await;
+        future::join_all(
+            images_to_process
+                .iter()
+                .filter_map(|(_, path)| path.parent())
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .map(tokio::fs::create_dir_all),
         )
         .await;
 
-        if images.is_empty() {
-            println!(
-                "{}  {}",
-                INFO,
-                style("No images found to download").bold().blue()
-            );
+        // images are optimized as soon as they're downloaded
+        let download_optimizer = if cli.opt_only_on_validation {
+            None
         } else {
-            println!(
-                "{}  {}",
-                FOLDER,
-                style("Creating the folder structure...").bold().green()
-            );
-            tokio::fs::create_dir_all(download_path.clone()).await?;
-            future::join_all(
-                scales
-                    .iter()
-                    .map(|s| tokio::fs::create_dir_all(download_path.join(format!("{}.0x", s)))),
+            Some(&optimizer)
+        };
+        let stage = Instant::now();
+        let downloads = download_images(&images_to_process, client, download_optimizer).await;
+        let mut results = vec![];
+        for ((image, path), download) in images_to_process.iter().zip(downloads) {
+            match download {
+                Ok(result) => {
+                    report.add_file(path, FileKind::Downloaded, Some(&image.id), None);
+                    results.extend(result);
+                }
+                Err(e) => report.add_file(
+                    path,
+                    FileKind::Downloaded,
+                    Some(&image.id),
+                    Some(format!("{:#}", e)),
+                ),
+            }
+        }
+        for image in &images {
+            let path = download_path.join(image.relative_path(&path_template));
+            if !images_to_process.iter().any(|(_, p)| *p == path) {
+                report.add_kept_file(&path, FileKind::Downloaded, Some(&image.id));
+            }
+        }
+        report.add_stage("download", stage);
+
+        let stage = Instant::now();
+        let sized_images = resize_images(
+            &images,
+            &download_path,
+            &path_template,
+            size_outputs,
+            &optimizer,
+            report,
+        )
+        .await;
+        // resized images are new files, they need to be optimized like the downloaded ones
+        if let Some(optimizer) = download_optimizer.filter(|_| !sized_images.is_empty()) {
+            let paths: Vec<_> = sized_images
+                .iter()
+                .map(|i| download_path.join(i.relative_path(&path_template)))
+                .collect();
+            let progress = progress::Stage::start(3, STAGES, "Optimizing", "files", paths.len());
+            results.extend(optimizer.optimize_all(paths, &progress).await);
+            progress.finish();
+        }
+        report.add_stage("resize", stage);
+        if download_optimizer.is_some() {
+            report.optimization =
+                Some(report_optimization(&results, cli.opt_report.as_deref()).await);
+        }
+
+        if !derived_formats.is_empty() {
+            let stage = Instant::now();
+            convert_images(
+                &converter::derived_images(&images, &derived_formats, &path_template),
+                &images_to_process,
+                &download_path,
+                &path_template,
+                conversion_options,
+                &optimizer,
+                report,
             )
             .await;
+            report.add_stage("convert", stage);
+        }
 
-            let images_to_process = get_images_info_to_process(
-                &images,
-                &download_path,
-                cli.download_only_unexisting_in_folder,
+        exported_images = with_derived_images(&images, &derived_formats, &path_template);
+        exported_images.extend(sized_images);
+
+        if let Some(languages) = &cli.codegen_languages {
+            let stage = Instant::now();
+            let codegen_path: PathBuf =
+                std::env::current_dir()?.join(cli.codegen_path.as_deref().unwrap_or("."));
+            generate_code(
+                &exported_images,
+                &cli.path,
+                &path_template,
+                languages,
+                &codegen_path,
+                report,
             )
             .await;
+            report.add_stage("codegen", stage);
+        }
+    }
 
-            download_images(&images_to_process, &client)
-                .await
-                .expect("Error downloading");
-            // optimizations doesn't seem to work with threads/futures
-            if !cli.opt_only_on_validation {
-                for img_info in images_to_process {
-                    let img = img_info.0;
-                    let final_path = img_info.1;
-                    if let Err(e) = optimize_image(
-                        &final_path,
-                        &img.format,
-                        cli.opt_png_level,
-                        cli.opt_jpg_level,
-                    ) {
-                        println!(
-                            "{} Error optimizing image {:?} => {:?}",
-                            ERROR, &final_path, e
-                        );
-                    } else {
-                        // validate that it exists
-                        let file_exists = Path::exists(&final_path);
-                        if !file_exists {
-                            println!(
-                                "{} {} {:?}",
-                                ERROR,
-                                style("FILE DELETED").red().bold(),
-                                &final_path
-                            );
-                        }
-                    }
+    let mut produced_files = exported_images
+        .iter()
+        .map(|i| download_path.join(i.relative_path(&path_template)))
+        .collect::<Vec<_>>();
+    if let Some(icons) = &cli.app_icons {
+        let stage = Instant::now();
+        produced_files.extend(
+            export_app_icons(
+                &frames,
+                icons,
+                client,
+                file,
+                &download_path,
+                &optimizer,
+                report,
+            )
+            .await,
+        );
+        report.add_stage("app_icons", stage);
+    }
+
+    // without images we can't know which files are stale
+    if !images.is_empty() {
+        update_owned_files(
+            &download_path,
+            &produced_files,
+            checked_prune_mode(prune_mode, &images, report),
+            &trash_path,
+            report,
+        );
+    }
+    info!(
+        "{}  {} {}  {}  {}",
+        THUMB,
+        style("We're done!").bold().green(),
+        HEART,
+        GIFT,
+        CRAB
+    );
+    Ok(())
+}
+
+/// Syncs the assets every time a new version of the file is saved, until the process is stopped
+async fn watch(cli: &Cli, interval: Duration, debounce: Duration) -> anyhow::Result<()> {
+    if cli.file_version.is_some() || cli.snapshot.is_some() {
+        return Err(anyhow::anyhow!(
+            "The file version is pinned, so it will never change. Remove --file-version and --snapshot."
+        ));
+    }
+    let (token, file_id) = match (&cli.personal_access_token, &cli.file_id) {
+        (Some(token), Some(file_id)) => (token, file_id),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Some arguments are missing. Check access token and file id."
+            ))
+        }
+    };
+    let client = get_client(token)?;
+    let download_path = std::env::current_dir()?.join(&cli.path);
+    let mut tracker = watcher::VersionTracker::default();
+    let mut cycle = 0;
+    info!(
+        "{}  {}",
+        CLOCK,
+        style(format!(
+            "Watching the file every {} secs...",
+            interval.as_secs()
+        ))
+        .bold()
+        .green()
+    );
+
+    loop {
+        match get_file_info(file_id, &client).await {
+            Ok(file) if tracker.observe(&file.version, Instant::now(), debounce) => {
+                cycle += 1;
+                let label = format!("version {}", file.version);
+                // failed syncs are retried in the next cycle
+                if sync_cycle(cli, &download_path, cycle, &label).await {
+                    tracker.synced(&file.version);
+                }
+            }
+            Ok(file) => {
+                if tracker.is_pending() {
+                    debug!(
+                        "{} Version {} may still be edited, waiting...",
+                        INFO, file.version
+                    );
                 }
             }
+            Err(e) => error!("{} Error getting the version of the file => {:?}", ERROR, e),
         }
-        println!(
-            "{}  {} {}  {}  {}",
-            THUMB,
-            style("We're done!").bold().green(),
-            HEART,
-            GIFT,
-            CRAB
-        );
-    } else {
-        println!(
+        let wait = if tracker.is_pending() {
+            interval.min(debounce)
+        } else {
+            interval
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Syncs the assets when the Figma webhook posts an update of the file, until the process is stopped
+async fn serve(cli: &Cli, host: &str, port: u16, passcode: &str) -> anyhow::Result<()> {
+    if cli.file_version.is_some() || cli.snapshot.is_some() {
+        return Err(anyhow::anyhow!(
+            "The file version is pinned, so it will never change. Remove --file-version and --snapshot."
+        ));
+    }
+    let file_id = match (&cli.personal_access_token, &cli.file_id) {
+        (Some(_), Some(file_id)) => file_id,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Some arguments are missing. Check access token and file id."
+            ))
+        }
+    };
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Unable to resolve the address {}:{}", host, port))?;
+    let download_path = std::env::current_dir()?.join(&cli.path);
+    let sync_requests = Arc::new(Notify::new());
+    let (addr, server) = webhook::bind(
+        addr,
+        webhook::Receiver::new(passcode, file_id)?,
+        sync_requests.clone(),
+    )?;
+    info!(
+        "{}  {}",
+        LINK,
+        style(format!("Listening to Figma webhooks on http://{}", addr))
+            .bold()
+            .green()
+    );
+
+    let syncs = async {
+        let mut cycle = 0;
+        loop {
+            sync_requests.notified().await;
+            cycle += 1;
+            sync_cycle(cli, &download_path, cycle, "webhook").await;
+        }
+    };
+    tokio::select! {
+        result = server => Ok(result?),
+        _ = syncs => Ok(()),
+    }
+}
+
+/// Runs a sync and prints what it changed. Returns false if it failed.
+async fn sync_cycle(cli: &Cli, download_path: &Path, cycle: usize, label: &str) -> bool {
+    let start = Instant::now();
+    let owned_files = pruner::OwnedFiles::read(download_path).unwrap_or_default();
+    let before = watcher::Fingerprints::read(download_path, &owned_files.files);
+
+    let mut report = RunReport::default();
+    let result = sync(cli, &mut report).await;
+    if let Err(e) = &result {
+        error!("{} Error syncing {} => {:?}", ERROR, label, e);
+        report.add_error(format!("{:#}", e));
+    }
+    report.finish(start);
+    watcher::CycleSummary::new(&before, &report).print(cycle, label);
+    if let Some(report_path) = &cli.report {
+        if let Err(e) = report.write_json(Path::new(report_path)).await {
+            error!(
+                "{} Error writing the report {:?} => {:?}",
+                ERROR, report_path, e
+            );
+        }
+    }
+    result.is_ok()
+}
+
+/// Writes or updates the manifest with the frames found in the documents
+async fn manifest(cli: &Cli, command: &ManifestCommand) -> anyhow::Result<()> {
+    if let ManifestCommand::Generate { path, force: false } = command {
+        if Path::new(path).exists() {
+            return Err(anyhow::anyhow!(
+                "The manifest {} already exists. Use `fad manifest update` or --force to overwrite it.",
+                path
+            ));
+        }
+    }
+    // the frames of a snapshot are enough, the images are not needed
+    let source = Source::new(cli, true).await?.ok_or_else(|| {
+        anyhow::anyhow!("Some arguments are missing. Check access token, file id or document ids.")
+    })?;
+    let frames = source
+        .frames()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No frames found, the manifest is not written"))?;
+    let files = manifest_generator::manifest_files(&frames);
+
+    match command {
+        ManifestCommand::Generate { path, .. } => {
+            let path = Path::new(path);
+            let manifest = manifest_generator::generate(
+                &files,
+                &cli.file_extensions,
+                &cli.file_scales,
+                &cli.path,
+            );
+            tokio::fs::write(path, manifest).await?;
+            info!(
+                "{}  {}",
+                THUMB,
+                style(format!(
+                    "Manifest with {} files written to {}",
+                    files.len(),
+                    path.display()
+                ))
+                .bold()
+                .green()
+            );
+        }
+        ManifestCommand::Update { path } => {
+            let path = Path::new(path);
+            let existing = tokio::fs::read_to_string(path).await.map_err(|e| {
+                anyhow::anyhow!(
+                    "Unable to read the manifest {}: {}. Use `fad manifest generate` to create it.",
+                    path.display(),
+                    e
+                )
+            })?;
+            let (manifest, added) = manifest_generator::update(&existing, &files)?;
+            for file in &added {
+                info!("    {} {}", style("new").green(), file);
+            }
+            if !added.is_empty() {
+                tokio::fs::write(path, manifest).await?;
+            }
+            info!(
+                "{}  {}",
+                THUMB,
+                style(format!(
+                    "{} new files added to {}",
+                    added.len(),
+                    path.display()
+                ))
+                .bold()
+                .green()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Saves the nodes of the documents, so the frames can be read later without network
+async fn snapshot(cli: &Cli, path: &Path) -> anyhow::Result<()> {
+    let (token, file_id, document_ids) =
+        match (&cli.personal_access_token, &cli.file_id, &cli.document_ids) {
+            (Some(token), Some(file_id), Some(document_ids)) => (token, file_id, document_ids),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Some arguments are missing. Check access token, file id or document ids."
+                ))
+            }
+        };
+    let client = get_client(token)?;
+    // the latest version is pinned, so the snapshot and its images always match
+    let file_version = match &cli.file_version {
+        Some(version) => version.clone(),
+        None => get_file_info(file_id, &client).await?.version,
+    };
+    let file = FileSource {
+        id: file_id,
+        version: Some(&file_version),
+    };
+    let mut snapshot = Snapshot::new(file_id, file_version.as_str());
+    for document_id in document_ids {
+        info!(
             "{}  {}",
-            ERROR,
-            style("Some arguments are missing. Check access token, file id or document ids.")
+            FRAME,
+            style(format!("Saving the nodes of {}...", document_id))
                 .bold()
-                .red(),
+                .green()
         );
+        snapshot.add(document_id, get_nodes(&client, file, document_id).await?)?;
     }
-    println!(
+    snapshot.write(path).await?;
+    info!(
+        "{}  {}",
+        THUMB,
+        style(format!(
+            "Snapshot of version {} saved to {}",
+            file_version,
+            path.display()
+        ))
+        .bold()
+        .green()
+    );
+    Ok(())
+}
+
+/// Prints the recent versions of the file, so one of them can be pinned with `--file-version`
+async fn versions(cli: &Cli, limit: usize) -> anyhow::Result<()> {
+    let (token, file_id) = match (&cli.personal_access_token, &cli.file_id) {
+        (Some(token), Some(file_id)) => (token, file_id),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Some arguments are missing. Check access token and file id."
+            ))
+        }
+    };
+    let client = get_client(token)?;
+    // the endpoint returns up to 50 versions per page
+    let url = format!(
+        "https://api.figma.com/v1/files/{}/versions?page_size={}",
+        file_id,
+        limit.clamp(1, 50)
+    );
+    debug!("{} {}", LINK, url);
+    let file_versions: FileVersions = client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    info!(
+        "{}  {}",
+        CLOCK,
+        style(format!("Versions of the file {}:", file_id))
+            .bold()
+            .green()
+    );
+    for version in file_versions.versions.iter().take(limit) {
+        info!(
+            "    {}  {}  {}  {}",
+            style(&version.id).bold(),
+            version.created_at,
+            version.user.handle,
+            version.label.as_deref().unwrap_or("-"),
+        );
+    }
+    Ok(())
+}
+
+async fn get_file_info(file_id: &str, client: &Client) -> anyhow::Result<FileInfo> {
+    // depth=1 avoids downloading the whole document
+    let url = format!("https://api.figma.com/v1/files/{}?depth=1", file_id);
+    debug!("{} {}", LINK, url);
+    let info = client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(info)
+}
+
+fn print_elapsed(start: Instant) {
+    info!(
         "{}  {}",
         CLOCK,
         style(format!("It took {} secs.", start.elapsed().as_secs()))
             .bold()
             .blue(),
     );
-    Ok(())
 }
 
 async fn get_cli() -> anyhow::Result<Cli> {
     let cli: Cli = Cli::from_args();
     match tokio::fs::read_to_string(&cli.config_path).await {
         Ok(config_str) => {
-            let mut cli_from_file: Cli = toml::from_str(&config_str).map_err(|e| {
+            let mut cli_from_file: Cli = toml::from_str(&config_str).inspect_err(|_| {
                 // NOTE: this should never happen while the cli options keep being all optional.
                 // we keep it here just in case something changes in the future.
-                println!(
+                error!(
                     "{}  {}",
                     ERROR,
                     style("An error occurred trying to parse the config file.")
                         .bold()
                         .red(),
                 );
-                e
             })?;
             cli_from_file.add_non_defaults(cli);
             Ok(cli_from_file)
@@ -198,7 +806,7 @@ async fn get_cli() -> anyhow::Result<Cli> {
 }
 
 fn get_client(token: &str) -> Result<Client, reqwest::Error> {
-    println!("{}  {}", ROCKET, style("Preparing...").bold().green());
+    info!("{}  {}", ROCKET, style("Preparing...").bold().green());
     let mut headers = header::HeaderMap::new();
     headers.insert(
         "X-Figma-Token",
@@ -207,49 +815,118 @@ fn get_client(token: &str) -> Result<Client, reqwest::Error> {
     reqwest::Client::builder().default_headers(headers).build()
 }
 
+/// File and documents exported by a run, and where their nodes are read from
+struct Source {
+    file_id: String,
+    file_version: Option<String>,
+    document_ids: Vec<String>,
+    snapshot: Option<Snapshot>,
+    client: Option<Client>,
+}
+
+impl Source {
+    /// Reads the snapshot, if any. None if some arguments are missing.
+    /// `offline` runs don't need the access token when the nodes are read from a snapshot.
+    async fn new(cli: &Cli, offline: bool) -> anyhow::Result<Option<Self>> {
+        let snapshot = match &cli.snapshot {
+            Some(path) => Some(Snapshot::read(Path::new(path)).await?),
+            None => None,
+        };
+        if let Some(snapshot) = &snapshot {
+            snapshot.check_source(cli.file_id.as_deref(), cli.file_version.as_deref())?;
+        }
+        let offline = offline && snapshot.is_some();
+        let file_id = cli
+            .file_id
+            .clone()
+            .or_else(|| snapshot.as_ref().map(|s| s.file_id.clone()));
+        let document_ids = cli
+            .document_ids
+            .clone()
+            .or_else(|| snapshot.as_ref().map(Snapshot::document_ids));
+        // the images of a snapshot are rendered from its version
+        let file_version = cli
+            .file_version
+            .clone()
+            .or_else(|| snapshot.as_ref().map(|s| s.file_version.clone()));
+        let (client, file_id, document_ids) =
+            match (&cli.personal_access_token, file_id, document_ids) {
+                (Some(token), Some(file_id), Some(document_ids)) => {
+                    (Some(get_client(token)?), file_id, document_ids)
+                }
+                (None, Some(file_id), Some(document_ids)) if offline => {
+                    (None, file_id, document_ids)
+                }
+                _ => return Ok(None),
+            };
+        Ok(Some(Self {
+            file_id,
+            file_version,
+            document_ids,
+            snapshot,
+            client,
+        }))
+    }
+
+    fn file(&self) -> FileSource<'_> {
+        FileSource {
+            id: &self.file_id,
+            version: self.file_version.as_deref(),
+        }
+    }
+
+    /// The client, if the run needs network
+    fn client(&self) -> anyhow::Result<&Client> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The access token is missing"))
+    }
+
+    async fn frames(&self) -> anyhow::Result<Option<Frames>> {
+        let nodes = match &self.snapshot {
+            Some(snapshot) => NodesSource::Snapshot(snapshot),
+            None => NodesSource::Api(self.client()?, self.file()),
+        };
+        get_frames(nodes, &self.document_ids).await
+    }
+}
+
+/// Where the nodes of the documents are read from
+#[derive(Clone, Copy)]
+enum NodesSource<'a> {
+    Api(&'a Client, FileSource<'a>),
+    Snapshot(&'a Snapshot),
+}
+
 async fn get_frames(
-    file_id: &str,
+    nodes: NodesSource<'_>,
     document_ids: &[String],
-    client: &Client,
 ) -> anyhow::Result<Option<Frames>> {
     let mut frames: Frames = vec![];
+    let stage =
+        progress::Stage::start(1, STAGES, "Getting frames", "documents", document_ids.len());
 
     for document_id in document_ids {
-        let url = format!(
-            "https://api.figma.com/v1/files/{}/nodes?ids={}",
-            file_id, document_id,
-        );
-        println!(
-            "{}  {}\n{} {}",
+        info!(
+            "{}  {}",
             FRAME,
-            style("Getting Frames from...").bold().green(),
-            LINK,
-            url,
+            style("Getting Frames from...").bold().green()
         );
-        let mut page: Page = client.get(&url).send().await?.json().await.map_err(|e| {
+        let value = match nodes {
+            NodesSource::Api(client, file) => get_nodes(client, file, document_id).await?,
+            NodesSource::Snapshot(snapshot) => snapshot.nodes(document_id)?,
+        };
+        let page: Page = serde_json::from_value(value).inspect_err(|_| {
             let error_message = "Check the values of your configuration. Is the URL ok?";
-            println!("{}  {}", ERROR, style(error_message).bold().red());
-            e
+            error!("{}  {}", ERROR, style(error_message).bold().red());
         })?;
-        let document_node = page.nodes.remove(document_id).map(|doc| doc.document);
-        let frames_per_doc = document_node
-            .map(|doc| {
-                doc.children.map(|nodes| {
-                    nodes
-                        .into_iter()
-                        .filter(|node| node.node_type == NodeType::FRAME)
-                        .collect::<Frames>()
-                })
-            })
-            .flatten();
-
-        if let Some(mut f) = frames_per_doc {
-            frames.append(&mut f);
-        }
+        frames.append(&mut page.into_frames(document_id));
+        stage.inc();
     }
+    stage.finish();
 
     if frames.is_empty() {
-        println!(
+        error!(
             "{}  {}",
             ERROR,
             style("No frames found. Check your file id and document ids.")
@@ -262,15 +939,27 @@ async fn get_frames(
     }
 }
 
+/// Response of the nodes endpoint for a document, as Figma returns it
+async fn get_nodes(
+    client: &Client,
+    file: FileSource<'_>,
+    document_id: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let url = file.nodes_url(document_id);
+    debug!("{} {}", LINK, url);
+    Ok(client.get(&url).send().await?.json().await?)
+}
+
 async fn get_images(
     frames: &Option<Frames>,
     client: &Client,
-    file_id: &str,
-    scales: &[usize],
+    file: FileSource<'_>,
+    scales: &[Scale],
     formats: &[String],
     force_extensions: bool,
+    report: &mut RunReport,
 ) -> Vec<Image> {
-    println!("{}  {}", LINK, style("Getting URLs from...").bold().green());
+    info!("{}  {}", LINK, style("Getting URLs from...").bold().green());
     if let Some(frames) = frames {
         let mut free_images = vec![];
         let mut png_images = vec![];
@@ -280,19 +969,12 @@ async fn get_images(
 
         for frame in frames {
             let id = frame.id.as_str();
-            if force_extensions {
-                free_images.push(id);
-            } else {
-                match Path::new(&frame.name)
-                    .extension()
-                    .and_then(std::ffi::OsStr::to_str)
-                {
-                    Some("png") => png_images.push(id),
-                    Some("jpeg") | Some("jpg") => jpg_images.push(id),
-                    Some("pdf") => pdf_images.push(id),
-                    Some("svg") => svg_images.push(id),
-                    _ => free_images.push(id),
-                }
+            match frame_format(frame, force_extensions) {
+                Some("png") => png_images.push(id),
+                Some("jpg") => jpg_images.push(id),
+                Some("pdf") => pdf_images.push(id),
+                Some("svg") => svg_images.push(id),
+                _ => free_images.push(id),
             }
         }
 
@@ -311,7 +993,7 @@ async fn get_images(
 
             if !image_ids.is_empty() {
                 futures.push(
-                    get_images_url_collection(image_ids, client, file_id, scale, format)
+                    get_images_url_collection(image_ids, client, file, scale, format)
                         .map_ok(move |urls| to_images(frames, &urls, scale, format)),
                 );
             }
@@ -328,39 +1010,94 @@ async fn get_images(
             }
         }
 
-        future::join_all(futures)
-            .await
-            .into_iter()
-            .filter_map(std::result::Result::ok)
-            .flatten()
-            .collect::<Vec<_>>()
+        let stage = progress::Stage::start(2, STAGES, "Resolving urls", "requests", futures.len());
+        let stage = &stage;
+        let results =
+            future::join_all(futures.into_iter().map(|f| f.inspect(move |_| stage.inc()))).await;
+        stage.finish();
+
+        let mut images = vec![];
+        for result in results {
+            match result {
+                Ok(mut i) => images.append(&mut i),
+                Err(e) => {
+                    report.add_error(format!("Error getting images url from Figma API: {}", e))
+                }
+            }
+        }
+        images
     } else {
         vec![]
     }
 }
 
+/// Format set by the extension of the frame name, None if the frame uses the configured formats
+fn frame_format(frame: &Node, force_extensions: bool) -> Option<&'static str> {
+    if force_extensions {
+        return None;
+    }
+    match Path::new(&frame.name)
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+    {
+        Some("png") => Some("png"),
+        Some("jpeg") | Some("jpg") => Some("jpg"),
+        Some("pdf") => Some("pdf"),
+        Some("svg") => Some("svg"),
+        _ => None,
+    }
+}
+
+/// Images that would be requested to Figma, without urls
+fn requested_images(
+    frames: &Option<Frames>,
+    scales: &[Scale],
+    formats: &[String],
+    force_extensions: bool,
+) -> Vec<Image> {
+    let mut images = vec![];
+    for scale in scales {
+        for frame in frames.as_deref().unwrap_or_default() {
+            let frame_formats = match frame_format(frame, force_extensions) {
+                Some(format) => vec![format],
+                None => formats.iter().map(String::as_str).collect(),
+            };
+            for format in frame_formats {
+                images.push(
+                    Image::new(
+                        frame.id.clone(),
+                        &frame.name,
+                        *scale,
+                        format.to_owned(),
+                        String::new(),
+                    )
+                    .with_page(frame.page_name.clone()),
+                );
+            }
+        }
+    }
+    images
+}
+
 async fn get_images_url_collection(
     image_ids: &str,
     client: &Client,
-    file_id: &str,
-    scale: usize,
+    file: FileSource<'_>,
+    scale: Scale,
     format: &str,
 ) -> Result<ImageUrlCollection, reqwest::Error> {
-    let url = format!(
-        "https://api.figma.com/v1/images/{}?ids={}&scale={}&format={}",
-        file_id, image_ids, scale, format,
-    );
-    println!("{} Url Collection  {}", LINK, url);
+    let url = file.images_url(image_ids, scale, format);
+    debug!("{} Url Collection  {}", LINK, url);
 
     match client.get(&url).send().await {
         Err(e) => {
-            println!("{} Error getting images url from Figma API {:?}", ERROR, e);
+            error!("{} Error getting images url from Figma API {:?}", ERROR, e);
             Err(e)
         }
         Ok(response) => {
             if let Err(e) = response.error_for_status_ref() {
                 let error_text = response.text().await?;
-                println!(
+                error!(
                     "{} Error {:?} parsing images url from Figma API: {}",
                     ERROR,
                     e.status(),
@@ -371,7 +1108,7 @@ async fn get_images_url_collection(
                 match response.json::<ImageUrlCollection>().await {
                     Ok(iuc) => Ok(iuc),
                     Err(e) => {
-                        println!(
+                        error!(
                             "{} Error parsing images url from Figma API: NOT JSON {:?}",
                             ERROR, e,
                         );
@@ -383,7 +1120,7 @@ async fn get_images_url_collection(
     }
 }
 
-fn to_images(frames: &[Node], urls: &ImageUrlCollection, scale: usize, format: &str) -> Vec<Image> {
+fn to_images(frames: &[Node], urls: &ImageUrlCollection, scale: Scale, format: &str) -> Vec<Image> {
     frames
         .iter()
         .filter_map(|f| {
@@ -395,6 +1132,7 @@ fn to_images(frames: &[Node], urls: &ImageUrlCollection, scale: usize, format: &
                     format.to_owned(),
                     url.to_owned(),
                 )
+                .with_page(f.page_name.clone())
             })
         })
         .collect()
@@ -403,22 +1141,20 @@ fn to_images(frames: &[Node], urls: &ImageUrlCollection, scale: usize, format: &
 async fn get_images_info_to_process<'a>(
     images: &'a [Image],
     download_path: &'a Path,
+    template: &PathTemplate,
     download_only_unexisting_in_folder: bool,
 ) -> Vec<(&'a Image, PathBuf)> {
     let mut images_to_process: Vec<(&Image, PathBuf)> = vec![];
     for i in images {
-        let path = if i.scale == 1 {
-            download_path.to_owned()
-        } else {
-            download_path.join(format!("{}.0x", i.scale))
-        };
-        let final_path = path.join(format!("{}.{}", i.name.trim(), i.format));
+        let final_path = download_path.join(i.relative_path(template));
 
         if !download_only_unexisting_in_folder {
             images_to_process.push((i, final_path))
         } else {
             let file_exists = tokio::fs::metadata(&final_path).await.is_ok();
-            if !file_exists {
+            if file_exists {
+                debug!("{} Keeping existing image {:?}", INFO, final_path);
+            } else {
                 images_to_process.push((i, final_path));
             }
         }
@@ -426,78 +1162,518 @@ async fn get_images_info_to_process<'a>(
     images_to_process
 }
 
-async fn download_images(images: &[(&Image, PathBuf)], client: &Client) -> anyhow::Result<()> {
-    println!(
+/// Downloads the images and optimizes them if there's an optimizer.
+/// Returns the result of every image, in the same order.
+async fn download_images(
+    images: &[(&Image, PathBuf)],
+    client: &Client,
+    optimizer: Option<&Optimizer>,
+) -> Vec<anyhow::Result<Option<OptimizationResult>>> {
+    info!(
         "{}  {}",
         DOWN,
         style("Downloading images...").bold().green()
     );
-    let futures = images.iter().map(move |i| async move {
-        let bytes = client.get(&i.0.url).send().await?.bytes().await?;
-        match tokio::fs::File::create(&i.1).await {
-            Ok(mut file) => {
-                if let Err(e) = file.write_all(&bytes).await {
-                    println!("{} Error writing image {:?} => {:?}", ERROR, i.1, e);
-                } else {
-                    println!(
-                        "{} {} {:?}",
-                        LINK,
-                        style("Image Downloaded").blue().bold(),
-                        i.1
-                    );
+    let stage = progress::Stage::start(3, STAGES, "Downloading", "files", images.len());
+    let stage = &stage;
+    let futures = images.iter().map(move |i| {
+        download_image(i, client, optimizer, stage).inspect(move |result| {
+            if let Ok(Some(optimization)) = result {
+                if optimization.error.is_none() {
+                    stage.inc_optimized();
                 }
             }
+            stage.inc();
+        })
+    });
+
+    let results = future::join_all(futures).await;
+    stage.finish();
+    results
+}
+
+async fn download_image(
+    i: &(&Image, PathBuf),
+    client: &Client,
+    optimizer: Option<&Optimizer>,
+    stage: &progress::Stage,
+) -> anyhow::Result<Option<OptimizationResult>> {
+    trace!("{} Downloading {:?} from {}", DOWN, i.1, i.0.url);
+    let bytes = client.get(&i.0.url).send().await?.bytes().await?;
+    stage.add_bytes(bytes.len() as u64);
+    match tokio::fs::File::create(&i.1).await {
+        Ok(mut file) => {
+            if let Err(e) = file.write_all(&bytes).await {
+                error!("{} Error writing image {:?} => {:?}", ERROR, i.1, e);
+                return Err(e.into());
+            } else {
+                info!(
+                    "{} {} {:?}",
+                    LINK,
+                    style("Image Downloaded").blue().bold(),
+                    i.1
+                );
+                if let Some(optimizer) = optimizer {
+                    // pending writes must be done before optimizing the file
+                    file.flush().await?;
+                    drop(file);
+                    let result = optimizer.optimize(i.1.clone(), i.0.format.clone()).await;
+                    return Ok(Some(result));
+                }
+            }
+        }
+        Err(e) => {
+            error!("{} Error creating image {:?} => {:?}", ERROR, i.1, e);
+            return Err(e.into());
+        }
+    }
+    Ok(None)
+}
+
+fn report_collisions(collisions: &[collisions::Collision]) {
+    if collisions.is_empty() {
+        return;
+    }
+    if collisions.iter().all(|c| c.renamed.is_empty()) {
+        warn!(
+            "{}  {}",
+            ERROR,
+            style("Some frames overwrite each other and only the last one is kept. Rename them in Figma or use --on-collision")
+                .bold()
+                .yellow()
+        );
+        for collision in collisions {
+            warn!("    {}", collision.describe());
+        }
+        return;
+    }
+    info!(
+        "{}  {}",
+        INFO,
+        style("Some frames would overwrite each other and have been renamed")
+            .bold()
+            .yellow()
+    );
+    for collision in collisions {
+        info!("    {}", collision.describe());
+    }
+}
+
+async fn report_optimization(
+    results: &[OptimizationResult],
+    report_path: Option<&str>,
+) -> OptimizationSummary {
+    let summary = OptimizationSummary::from_results(results);
+    summary.print();
+    if let Some(report_path) = report_path {
+        if let Err(e) = summary.write_json(Path::new(report_path)).await {
+            error!(
+                "{} Error writing the optimization report {:?} => {:?}",
+                ERROR, report_path, e
+            );
+        }
+    }
+    summary
+}
+
+/// Converts the downloaded images into their derived images, in the workers of the optimizer
+async fn convert_images(
+    derived_images: &[(&Image, converter::DerivedFormat, Image)],
+    images: &[(&Image, PathBuf)],
+    download_path: &Path,
+    template: &PathTemplate,
+    options: converter::ConversionOptions,
+    optimizer: &Optimizer,
+    report: &mut RunReport,
+) {
+    let conversions = derived_images
+        .iter()
+        .map(|(img, format, derived)| {
+            let path = download_path.join(img.relative_path(template));
+            let target = download_path.join(derived.relative_path(template));
+            (img, *format, path, target)
+        })
+        // kept images are not converted again
+        .filter(|(_, _, path, _)| images.iter().any(|(_, p)| p == path))
+        .collect::<Vec<_>>();
+    let results = future::join_all(conversions.iter().map(|(_, format, path, target)| {
+        let (format, path, target) = (*format, path.clone(), target.clone());
+        optimizer.run_blocking(move || converter::convert_image(&path, &target, format, options))
+    }))
+    .await;
+
+    for ((img, format, path, target), result) in conversions.into_iter().zip(results) {
+        match result {
+            Ok(()) => {
+                info!(
+                    "{} {} {:?}",
+                    FRAME,
+                    style("Image Converted").blue().bold(),
+                    target
+                );
+                report.add_file(&target, FileKind::Converted, Some(&img.id), None);
+            }
             Err(e) => {
-                println!("{} Error creating image {:?} => {:?}", ERROR, i.1, e);
+                error!(
+                    "{} Error converting image {:?} to {} => {:?}",
+                    ERROR,
+                    path,
+                    format.extension(),
+                    e
+                );
+                let error = Some(format!("{:#}", e));
+                report.add_file(&target, FileKind::Converted, Some(&img.id), error);
             }
         }
-        Ok::<(), anyhow::Error>(())
-    });
+    }
+}
 
-    future::join_all(futures).await;
-    Ok(())
+/// Writes the size outputs next to the downloaded images and returns the images produced.
+/// The images are resized by the workers of the optimizer.
+async fn resize_images(
+    images: &[Image],
+    download_path: &Path,
+    template: &PathTemplate,
+    outputs: &[resizer::SizeOutput],
+    optimizer: &Optimizer,
+    report: &mut RunReport,
+) -> Vec<Image> {
+    let plan = resizer::plan(images, outputs)
+        .into_iter()
+        .map(|(output, source, target)| {
+            let source_path = download_path.join(source.relative_path(template));
+            let target_path = download_path.join(target.relative_path(template));
+            (output, source, target, source_path, target_path)
+        })
+        .collect::<Vec<_>>();
+    let results = future::join_all(plan.iter().map(|(output, _, _, source_path, target_path)| {
+        let (output, source_path, target_path) =
+            ((*output).clone(), source_path.clone(), target_path.clone());
+        optimizer.run_blocking(move || resizer::resize_file(&source_path, &target_path, &output))
+    }))
+    .await;
+
+    let mut resized = vec![];
+    for ((_, source, target, source_path, target_path), result) in plan.into_iter().zip(results) {
+        match result {
+            Ok(()) => {
+                info!(
+                    "{} {} {:?}",
+                    FRAME,
+                    style("Image Resized").blue().bold(),
+                    target_path
+                );
+                report.add_file(&target_path, FileKind::Resized, Some(&source.id), None);
+                resized.push(target);
+            }
+            Err(e) => {
+                error!(
+                    "{} Error resizing image {:?} => {:?}",
+                    ERROR, source_path, e
+                );
+                let error = Some(format!("{:#}", e));
+                report.add_file(&target_path, FileKind::Resized, Some(&source.id), error);
+            }
+        }
+    }
+    resized
 }
 
-fn optimize_image(
-    path: &Path,
-    extension: &str,
-    opt_png_level: Option<u8>,
-    opt_jpg_level: Option<u8>,
-) -> anyhow::Result<()> {
-    match extension {
-        "jpg" => {
-            if let Some(lvl) = opt_jpg_level {
-                print_optimizing_image(path);
-                let img = image::open(path)?;
-                let dim = image::image_dimensions(path)?;
-                let mut fw = std::fs::File::create(path)?;
-                let mut enc = image::jpeg::JpegEncoder::new_with_quality(&mut fw, lvl);
-                enc.encode(&img.to_bytes(), dim.0, dim.1, img.color())?;
-            } else {
-                println!("Are you trying to optimize this image {:?}? Don't forget to set the --opt-jpg-level value", path);
+/// The prune mode of the run, None if some images or app icons failed
+fn checked_prune_mode(
+    prune_mode: Option<pruner::PruneMode>,
+    images: &[Image],
+    report: &RunReport,
+) -> Option<pruner::PruneMode> {
+    if prune_mode.is_some() && !pruner::can_prune(images, report) {
+        warn!(
+            "{}  {}",
+            ERROR,
+            style("Some frames failed, so the stale files are not pruned")
+                .bold()
+                .yellow()
+        );
+        return None;
+    }
+    prune_mode
+}
+
+/// Records the files produced by this run and prunes the ones produced by previous runs
+/// that are no longer exported. Only the recorded files are ever pruned.
+fn update_owned_files(
+    download_path: &Path,
+    produced_files: &[PathBuf],
+    prune_mode: Option<pruner::PruneMode>,
+    trash_path: &Path,
+    report: &mut RunReport,
+) {
+    let result = (|| {
+        let previous = pruner::OwnedFiles::read(download_path)?;
+        let mut current = pruner::OwnedFiles::from_paths(
+            download_path,
+            produced_files.iter().map(PathBuf::as_path),
+        );
+        let stale_files = previous.stale_files(&current);
+
+        if let Some(mode) = prune_mode {
+            let message = match mode {
+                pruner::PruneMode::List => "Stale files that would be pruned:",
+                _ => "Pruning stale files...",
+            };
+            info!("{}  {}", INFO, style(message).bold().blue());
+            for (file, result) in pruner::prune(download_path, &stale_files, mode, trash_path) {
+                match result {
+                    Ok(()) => {
+                        info!("    {}", style(&file).yellow());
+                        if mode != pruner::PruneMode::List {
+                            report.pruned.push(file);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{} Error pruning {} => {:?}", ERROR, file, e);
+                        report.add_error(format!("Error pruning {}: {:#}", file, e));
+                    }
+                }
             }
         }
-        "png" => {
-            if let Some(lvl) = opt_png_level {
-                print_optimizing_image(path);
-                let inf = oxipng::InFile::from(path);
-                let ouf = oxipng::OutFile::Path(Some(path.into()));
-                let opts = oxipng::Options::from_preset(lvl);
-                oxipng::optimize(&inf, &ouf, &opts)?;
-            } else {
-                println!("Are you trying to optimize this image {:?}? Don't forget to set the --opt-png-level value", path);
+
+        // stale files that are still there keep being owned by fad
+        current.files.extend(
+            stale_files
+                .into_iter()
+                .filter(|f| download_path.join(f).is_file()),
+        );
+        current.write(download_path)
+    })();
+
+    if let Err(e) = result {
+        error!("{} Error updating the record of files => {:?}", ERROR, e);
+        report.add_error(format!("Error updating the record of files: {:#}", e));
+    }
+}
+
+/// Adds the downloaded, derived and resized images to the plan
+fn plan_images(
+    plan: &mut planner::Plan,
+    images: &[Image],
+    skipped: &[Image],
+    template: &PathTemplate,
+    derived_formats: &[converter::DerivedFormat],
+    size_outputs: &[resizer::SizeOutput],
+    keep_existing: bool,
+) {
+    use planner::PlanStatus;
+
+    let download_path = plan.download_path.clone();
+    for image in images {
+        let path = download_path.join(image.relative_path(template));
+        let status = PlanStatus::of(&path, keep_existing);
+        plan.add(&path, status, Some(&image.id));
+    }
+    for (image, _, derived) in converter::derived_images(images, derived_formats, template) {
+        let path = download_path.join(image.relative_path(template));
+        let derived = download_path.join(derived.relative_path(template));
+        // images are only converted when they're downloaded
+        if PlanStatus::of(&path, keep_existing) != PlanStatus::Kept {
+            plan.add(&derived, PlanStatus::of(&derived, false), Some(&image.id));
+        } else if derived.exists() {
+            plan.add(&derived, PlanStatus::Kept, Some(&image.id));
+        } else {
+            plan.skip(&derived, Some(&image.id), "the source image is kept");
+        }
+    }
+    for (_, source, target) in resizer::plan(images, size_outputs) {
+        let path = download_path.join(target.relative_path(template));
+        plan.add(&path, PlanStatus::of(&path, false), Some(&source.id));
+    }
+    for image in skipped {
+        let path = download_path.join(image.relative_path(template));
+        plan.skip(&path, Some(&image.id), "Figma can't render it");
+    }
+}
+
+fn plan_app_icons(plan: &mut planner::Plan, frames: &Option<Frames>, icons: &[app_icon::AppIcon]) {
+    let download_path = plan.download_path.clone();
+    for icon in icons {
+        let folder = icon.folder(&download_path);
+        match find_app_icon_frame(frames, icon) {
+            Some(frame) => {
+                for file in app_icon::bundle_files() {
+                    let path = folder.join(file);
+                    let status = planner::PlanStatus::of(&path, false);
+                    plan.add(&path, status, Some(&frame.id));
+                }
             }
+            None => plan.skip(&folder, None, "the app icon frame is not found"),
+        }
+    }
+}
+
+/// Adds the recorded files that would be pruned
+fn plan_pruning(plan: &mut planner::Plan, prune_mode: Option<pruner::PruneMode>) {
+    if matches!(prune_mode, None | Some(pruner::PruneMode::List)) {
+        return;
+    }
+    let previous = match pruner::OwnedFiles::read(&plan.download_path) {
+        Ok(previous) => previous,
+        Err(e) => {
+            error!("{} Error reading the record of files => {:?}", ERROR, e);
+            return;
+        }
+    };
+    let current = pruner::OwnedFiles {
+        files: plan
+            .files
+            .iter()
+            .filter(|f| f.status != planner::PlanStatus::Skipped)
+            .map(|f| f.path.clone())
+            .collect(),
+    };
+    for file in previous.stale_files(&current) {
+        let path = plan.download_path.join(&file);
+        if path.is_file() {
+            plan.add(&path, planner::PlanStatus::Deleted, None);
         }
-        _ => (),
     }
-    Ok(())
 }
 
-fn print_optimizing_image(path: &Path) {
-    println!(
-        "{} {} {:?} ",
-        FRAME,
-        style("Optimizing image").yellow().bold(),
-        &path
+/// Downloads every app icon frame at the scale needed by its largest icon and generates its bundle
+async fn export_app_icons(
+    frames: &Option<Frames>,
+    icons: &[app_icon::AppIcon],
+    client: &Client,
+    file: FileSource<'_>,
+    download_path: &Path,
+    optimizer: &Optimizer,
+    report: &mut RunReport,
+) -> Vec<PathBuf> {
+    info!(
+        "{}  {}",
+        GIFT,
+        style("Generating app icons...").bold().green()
     );
+    let mut generated = vec![];
+    for icon in icons {
+        let frame = match find_app_icon_frame(frames, icon) {
+            Some(frame) => frame,
+            None => {
+                error!("{} App icon frame {} not found", ERROR, icon.name);
+                report.add_error(format!("App icon frame {} not found", icon.name));
+                continue;
+            }
+        };
+        let scale = app_icon::download_scale(frame.absolute_bounding_box);
+
+        let result = async {
+            let urls = get_images_url_collection(&frame.id, client, file, scale, "png").await?;
+            let url = urls
+                .images
+                .get(&frame.id)
+                .ok_or_else(|| anyhow::anyhow!("Figma didn't render the frame"))?;
+            let bytes = client.get(url).send().await?.bytes().await?;
+            let (icon, folder) = (icon.clone(), icon.folder(download_path));
+            optimizer
+                .run_blocking(move || app_icon::generate(&bytes, &icon, &folder))
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(files) => {
+                for file in files {
+                    info!(
+                        "{} {} {:?}",
+                        FRAME,
+                        style("App Icon Generated").blue().bold(),
+                        file
+                    );
+                    report.add_file(&file, FileKind::AppIcon, Some(&frame.id), None);
+                    generated.push(file);
+                }
+            }
+            Err(e) => {
+                error!(
+                    "{} Error generating app icon {} => {:?}",
+                    ERROR, icon.name, e
+                );
+                report.add_error(format!("Error generating app icon {}: {:#}", icon.name, e));
+            }
+        }
+    }
+    generated
+}
+
+fn find_app_icon_frame<'a>(
+    frames: &'a Option<Frames>,
+    icon: &app_icon::AppIcon,
+) -> Option<&'a Node> {
+    frames
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|f| is_app_icon_frame(f, icon))
+}
+
+fn is_app_icon_frame(frame: &Node, icon: &app_icon::AppIcon) -> bool {
+    Path::new(&frame.name)
+        .file_stem()
+        .and_then(std::ffi::OsStr::to_str)
+        == Some(icon.name.as_str())
+}
+
+/// Frames exported as images, the app icon frames are left out
+fn without_app_icons(frames: &Option<Frames>, icons: &[app_icon::AppIcon]) -> Option<Frames> {
+    frames.as_ref().map(|frames| {
+        frames
+            .iter()
+            .filter(|f| !icons.iter().any(|icon| is_app_icon_frame(f, icon)))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Adds the images that are derived locally to the ones downloaded from Figma
+fn with_derived_images(
+    images: &[Image],
+    formats: &[converter::DerivedFormat],
+    template: &PathTemplate,
+) -> Vec<Image> {
+    let mut all_images = images.to_vec();
+    all_images.extend(
+        converter::derived_images(images, formats, template)
+            .into_iter()
+            .map(|(_, _, derived)| derived),
+    );
+    all_images
+}
+
+async fn generate_code(
+    images: &[Image],
+    base_path: &str,
+    template: &PathTemplate,
+    languages: &[String],
+    path: &Path,
+    report: &mut RunReport,
+) {
+    info!(
+        "{}  {}",
+        GIFT,
+        style("Generating asset references...").bold().green()
+    );
+    match codegen::generate(images, Path::new(base_path), template, languages, path).await {
+        Ok(files) => {
+            for file in files {
+                info!(
+                    "{} {} {:?}",
+                    LINK,
+                    style("Code Generated").blue().bold(),
+                    file
+                );
+            }
+        }
+        Err(e) => {
+            error!("{} Error generating asset references => {:?}", ERROR, e);
+            report.add_error(format!("Error generating asset references: {:#}", e));
+        }
+    }
 }
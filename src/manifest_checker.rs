@@ -1,9 +1,14 @@
-use crate::{emojis, models::Manifest};
+use crate::{
+    emojis,
+    models::{Manifest, Node},
+    naming::NamingPolicy,
+};
 use async_trait::async_trait;
 use console::style;
+use log::{error, info};
 use scan_dir::ScanDir;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     error::Error,
     fmt::{Display, Formatter},
     path::{Path, PathBuf},
@@ -73,7 +78,10 @@ impl ManifestReader for TokioManifestReader<'_> {
                     .display()
                     .to_string();
 
-                all_files.push(entry_str);
+                // the record of the files produced by fad is not an asset
+                if entry_str != crate::pruner::RECORD_FILE_NAME {
+                    all_files.push(entry_str);
+                }
             }
         });
         if let Err(errors) = walk_result {
@@ -88,15 +96,25 @@ impl ManifestReader for TokioManifestReader<'_> {
 
 pub struct ManifestChecker<T: ManifestReader> {
     reader: T,
+    naming: NamingPolicy,
 }
 
 #[allow(clippy::use_self)]
 impl<T: ManifestReader> ManifestChecker<T> {
     pub fn new(reader: T) -> Self {
-        Self { reader }
+        Self {
+            reader,
+            naming: NamingPolicy::default(),
+        }
     }
 
-    pub fn with_tokio_reader(manifest_path: &Path) -> ManifestChecker<TokioManifestReader> {
+    /// Applies the naming policy used when downloading to the expected names
+    pub fn with_naming_policy(mut self, naming: NamingPolicy) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    pub fn with_tokio_reader(manifest_path: &Path) -> ManifestChecker<TokioManifestReader<'_>> {
         ManifestChecker::new(TokioManifestReader { manifest_path })
     }
 
@@ -104,13 +122,68 @@ impl<T: ManifestReader> ManifestChecker<T> {
         let manifest = self.reader.read_manifest().await?;
         let assets_dir_path = std::env::current_dir()?.join(&manifest.path);
         let assets = self.reader.read_assets(&assets_dir_path).await?;
-        Ok(Self::compare_results(manifest, assets, assets_dir_path))
+        Ok(Self::compare_results(
+            manifest,
+            assets,
+            assets_dir_path,
+            &self.naming,
+        ))
+    }
+
+    /// Compares the manifest with the frames of the Figma file instead of the download folder.
+    /// Missing assets are in the manifest but not in Figma, and new ones are frames not in the manifest.
+    pub async fn check_remote(
+        &self,
+        file_id: &str,
+        frames: &[Node],
+    ) -> Result<ManifestInfo, ManifestError> {
+        let manifest = self.reader.read_manifest().await?;
+        Ok(Self::compare_frames(manifest, frames, &self.naming).with_file_id(file_id))
+    }
+
+    fn compare_frames(manifest: Manifest, frames: &[Node], naming: &NamingPolicy) -> ManifestInfo {
+        // both names are compared as they would be written
+        let key = |name: &str| {
+            if Path::new(name).extension().is_none() {
+                naming.apply(name)
+            } else {
+                naming.apply_to_file_name(name)
+            }
+        };
+        let frame_keys = frames.iter().map(|f| key(&f.name)).collect::<BTreeSet<_>>();
+        let manifest_keys = manifest
+            .files
+            .iter()
+            .map(|f| key(f))
+            .collect::<BTreeSet<_>>();
+
+        let mut missing_assets = manifest
+            .files
+            .iter()
+            .filter(|f| !frame_keys.contains(&key(f)))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut new_assets = frames
+            .iter()
+            .filter(|f| !manifest_keys.contains(&key(&f.name)))
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>();
+
+        missing_assets.sort();
+        missing_assets.dedup();
+        new_assets.sort();
+        new_assets.dedup();
+
+        ManifestInfo::default()
+            .with_new_assets(new_assets)
+            .with_missing_assets(missing_assets)
     }
 
     fn compare_results(
         manifest: Manifest,
         assets: Vec<String>,
         assets_dir_path: PathBuf,
+        naming: &NamingPolicy,
     ) -> ManifestInfo {
         let mut new_assets = vec![];
         let extensions = &manifest.file_extensions;
@@ -124,18 +197,19 @@ impl<T: ManifestReader> ManifestChecker<T> {
 
                 // set the extensions if needed
                 if Path::new(&file).extension().is_none() {
+                    let file = naming.apply(&file);
                     for ext in extensions {
                         temp_files.push(format!("{}.{}", file, ext));
                     }
                 } else {
-                    temp_files.push(file);
+                    temp_files.push(naming.apply_to_file_name(&file));
                 }
 
                 // set the folder if needed
                 let mut temp_files_scales = vec![];
-                for scale in scales.iter().filter(|&s| s > &1) {
+                for folder in scales.iter().filter_map(|s| s.folder()) {
                     for tf in &temp_files {
-                        temp_files_scales.push(format!("{}.0x/{}", scale, tf));
+                        temp_files_scales.push(format!("{}/{}", folder, tf));
                     }
                 }
 
@@ -173,6 +247,8 @@ impl<T: ManifestReader> ManifestChecker<T> {
 #[derive(Default, Debug)]
 pub struct ManifestInfo {
     pub assets_dir_path: Option<PathBuf>,
+    /// Set when the manifest is compared with the frames of a Figma file
+    pub file_id: Option<String>,
     pub new_assets: Option<Vec<String>>,
     pub missing_assets: Option<Vec<String>>,
 }
@@ -184,6 +260,12 @@ impl ManifestInfo {
         self
     }
 
+    /// Adds the Figma file the manifest is compared with
+    pub fn with_file_id(mut self, file_id: impl Into<String>) -> Self {
+        self.file_id = Some(file_id.into());
+        self
+    }
+
     /// Adds new assets collection
     pub fn with_new_assets(mut self, assets: Vec<String>) -> Self {
         if !assets.is_empty() {
@@ -203,23 +285,26 @@ impl ManifestInfo {
     /// Prints the information about the manifest
     pub fn print_info(&self) {
         if let Some(missing_assets) = &self.missing_assets {
-            println!(
-                "{} {}",
-                emojis::ERROR,
-                style("There are some assets missing").red().bold()
-            );
+            let message = match &self.file_id {
+                Some(file_id) => format!(
+                    "There are some assets missing in the Figma file {}",
+                    file_id
+                ),
+                None => "There are some assets missing".to_string(),
+            };
+            error!("{} {}", emojis::ERROR, style(message).red().bold());
             for asset in missing_assets {
-                println!("    {}", style(asset).red());
+                error!("    {}", style(asset).red());
             }
         }
         if let Some(new_assets) = &self.new_assets {
-            println!(
-                "{} {}",
-                emojis::PLANT,
-                style("There are some new assets").green().bold()
-            );
+            let message = match &self.file_id {
+                Some(_) => "There are some frames not listed in the manifest",
+                None => "There are some new assets",
+            };
+            info!("{} {}", emojis::PLANT, style(message).green().bold());
             for asset in new_assets {
-                println!("    {}", style(asset).green());
+                info!("    {}", style(asset).green());
             }
         }
     }
@@ -229,6 +314,7 @@ impl ManifestInfo {
 mod tests {
 
     use super::*;
+    use crate::models::Scale;
 
     struct MockReader {
         pub read_manifest_result: Manifest,
@@ -259,6 +345,20 @@ mod tests {
         vec.into_iter().map(String::from).collect()
     }
 
+    fn frames_builder(names: Vec<&str>) -> Vec<Node> {
+        names
+            .into_iter()
+            .map(|name| Node {
+                id: "1:1".to_string(),
+                name: name.to_string(),
+                node_type: crate::models::NodeType::FRAME,
+                children: None,
+                absolute_bounding_box: None,
+                page_name: None,
+            })
+            .collect()
+    }
+
     #[tokio::test]
     async fn manifest_should_have_all_none_if_assets_and_manifest_are_ok() -> anyhow::Result<()> {
         let assets = asset_builder(vec!["a.jpg", "b.jpg"]);
@@ -305,7 +405,7 @@ mod tests {
     async fn manifest_should_use_extensions_when_files_have_no_extension() -> anyhow::Result<()> {
         let manifest = Manifest {
             file_extensions: vec!["png".to_string(), "svg".to_string()],
-            file_scales: vec![1],
+            file_scales: vec![Scale::ONE],
             files: asset_builder(vec!["a", "b"]),
             path: "".to_owned(),
         };
@@ -323,7 +423,7 @@ mod tests {
     async fn manifest_should_not_use_extensions_when_files_have_extensions() -> anyhow::Result<()> {
         let manifest = Manifest {
             file_extensions: vec!["png".to_string(), "svg".to_string()],
-            file_scales: vec![1],
+            file_scales: vec![Scale::ONE],
             files: asset_builder(vec!["a", "b", "c.svg", "d.jpg"]),
             path: "".to_owned(),
         };
@@ -341,7 +441,7 @@ mod tests {
     async fn manifest_should_use_scales() -> anyhow::Result<()> {
         let manifest = Manifest {
             file_extensions: vec!["png".to_string(), "svg".to_string()],
-            file_scales: vec![1, 2, 3],
+            file_scales: vec![Scale::ONE, Scale::new(2.0)?, Scale::new(3.0)?],
             files: asset_builder(vec!["a"]),
             path: "".to_owned(),
         };
@@ -361,4 +461,58 @@ mod tests {
         assert!(result.missing_assets.is_none());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn manifest_should_apply_the_naming_policy() -> anyhow::Result<()> {
+        let manifest =
+            Manifest::default().with_files(asset_builder(vec!["Arrow Left", "Niño.jpg"]));
+        let assets = asset_builder(vec!["arrow-left.png", "nino.jpg"]);
+        let mock_reader = MockReader::new(manifest, assets);
+        let naming = NamingPolicy {
+            case: Some(crate::naming::Case::Kebab),
+            transliterate: true,
+            ..NamingPolicy::default()
+        };
+        let checker = ManifestChecker::new(mock_reader).with_naming_policy(naming);
+        let result = checker.check().await?;
+
+        assert!(result.new_assets.is_none());
+        assert!(result.missing_assets.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remote_check_should_compare_the_manifest_with_the_frames() -> anyhow::Result<()> {
+        let manifest =
+            Manifest::default().with_files(asset_builder(vec!["arrow", "close.svg", "gone"]));
+        let mock_reader = MockReader::new(manifest, vec![]);
+        let checker = ManifestChecker::new(mock_reader);
+        let frames = frames_builder(vec!["arrow", "close.svg", "star", "star"]);
+        let result = checker.check_remote("abc", &frames).await?;
+
+        assert_eq!(result.file_id.as_deref(), Some("abc"));
+        assert_eq!(result.missing_assets, Some(asset_builder(vec!["gone"])));
+        assert_eq!(result.new_assets, Some(asset_builder(vec!["star"])));
+        assert!(result.assets_dir_path.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remote_check_should_apply_the_naming_policy() -> anyhow::Result<()> {
+        let manifest =
+            Manifest::default().with_files(asset_builder(vec!["arrow-left", "nino.jpg"]));
+        let mock_reader = MockReader::new(manifest, vec![]);
+        let naming = NamingPolicy {
+            case: Some(crate::naming::Case::Kebab),
+            transliterate: true,
+            ..NamingPolicy::default()
+        };
+        let checker = ManifestChecker::new(mock_reader).with_naming_policy(naming);
+        let frames = frames_builder(vec!["Arrow Left", "Niño.jpg"]);
+        let result = checker.check_remote("abc", &frames).await?;
+
+        assert!(result.new_assets.is_none());
+        assert!(result.missing_assets.is_none());
+        Ok(())
+    }
 }
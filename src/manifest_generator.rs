@@ -0,0 +1,185 @@
+use crate::models::{Node, Scale};
+use std::collections::BTreeSet;
+use toml_edit::{Array, DocumentMut, Item, Value};
+
+/// Names of the manifest for the frames, as they are in Figma, sorted and without duplicates.
+/// The names keep their extension, if any, so the manifest expects the same formats as the export.
+pub fn manifest_files(frames: &[Node]) -> Vec<String> {
+    frames
+        .iter()
+        .map(|frame| frame.name.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Writes a manifest with the files and the export settings
+pub fn generate(files: &[String], extensions: &[String], scales: &[Scale], path: &str) -> String {
+    let extensions = extensions
+        .iter()
+        .map(|e| Value::from(e.as_str()).to_string())
+        .collect::<Vec<_>>();
+    // toml arrays can't mix integers and floats
+    let scales = scales
+        .iter()
+        .map(|s| format!("{:?}", s.value()))
+        .collect::<Vec<_>>();
+    let mut manifest = format!(
+        "path = {}\nfile_extensions = [{}]\nfile_scales = [{}]\nfiles = [\n",
+        Value::from(path),
+        extensions.join(", "),
+        scales.join(", ")
+    );
+    for file in files {
+        manifest.push_str(&format!("  {},\n", Value::from(file.as_str())));
+    }
+    manifest.push_str("]\n");
+    manifest
+}
+
+/// Adds the files missing in the manifest at the end of its `files`,
+/// keeping the comments, the order and the format of the rest of the manifest.
+/// Returns the new manifest and the added files.
+pub fn update(manifest: &str, files: &[String]) -> anyhow::Result<(String, Vec<String>)> {
+    let mut document = manifest
+        .parse::<DocumentMut>()
+        .map_err(|e| anyhow::anyhow!("Error trying to parse the manifest: {}", e))?;
+    let entries = document
+        .entry("files")
+        .or_insert_with(|| Item::Value(Value::Array(Array::new())))
+        .as_array_mut()
+        .ok_or_else(|| anyhow::anyhow!("The files of the manifest must be an array"))?;
+
+    let existing = entries
+        .iter()
+        .filter_map(Value::as_str)
+        .map(String::from)
+        .collect::<BTreeSet<_>>();
+    let added = files
+        .iter()
+        .filter(|f| !existing.contains(*f))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // new entries look like the last one, so multiline arrays stay multiline
+    let prefix = entries
+        .iter()
+        .last()
+        .and_then(|last| last.decor().prefix())
+        .and_then(|prefix| prefix.as_str())
+        .map(|prefix| match prefix.rfind('\n') {
+            // without the comment of the previous entry
+            Some(newline) => prefix[newline..].to_string(),
+            None => " ".to_string(),
+        });
+    let multiline = prefix.as_ref().is_none_or(|p| p.starts_with('\n'));
+    let prefix = prefix.unwrap_or_else(|| "\n  ".to_string());
+    let trailing_comma = entries.trailing_comma() || (entries.is_empty() && multiline);
+    // the comment of the last entry is in the trailing of the array and must stay with it
+    let trailing = entries.trailing().as_str().unwrap_or_default().to_string();
+    let (last_comment, trailing) = match trailing.rfind('\n') {
+        Some(newline) => trailing.split_at(newline),
+        None if entries.is_empty() => ("", "\n"),
+        None => ("", trailing.as_str()),
+    };
+    for (i, file) in added.iter().enumerate() {
+        let mut value = Value::from(file.as_str());
+        if i == 0 {
+            value
+                .decor_mut()
+                .set_prefix(format!("{}{}", last_comment, prefix));
+        } else {
+            value.decor_mut().set_prefix(prefix.as_str());
+        }
+        entries.push_formatted(value);
+    }
+    if !added.is_empty() {
+        entries.set_trailing_comma(trailing_comma);
+        entries.set_trailing(trailing);
+    }
+    Ok((document.to_string(), added))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Manifest, NodeType};
+
+    fn frame(name: &str) -> Node {
+        Node {
+            id: "1:1".to_string(),
+            name: name.to_string(),
+            node_type: NodeType::FRAME,
+            children: None,
+            absolute_bounding_box: None,
+            page_name: None,
+        }
+    }
+
+    #[test]
+    fn generated_manifests_should_be_valid() {
+        let frames = vec![frame("close.svg"), frame("arrow"), frame("arrow")];
+        let files = manifest_files(&frames);
+        let manifest = generate(
+            &files,
+            &["png".to_string(), "webp".to_string()],
+            &[Scale::ONE, Scale::new(1.5).unwrap()],
+            "assets",
+        );
+
+        assert_eq!(
+            manifest,
+            "path = \"assets\"\nfile_extensions = [\"png\", \"webp\"]\nfile_scales = [1.0, 1.5]\nfiles = [\n  \"arrow\",\n  \"close.svg\",\n]\n"
+        );
+        let parsed: Manifest = toml::from_str(&manifest).unwrap();
+        assert_eq!(parsed.files, files);
+        assert_eq!(
+            parsed.file_scales,
+            vec![Scale::ONE, Scale::new(1.5).unwrap()]
+        );
+    }
+
+    #[test]
+    fn updates_should_keep_comments_and_order() -> anyhow::Result<()> {
+        let manifest = r#"# icons of the app
+path = "downloads"
+files = [
+  "zebra", # the mascot
+  "arrow", # points left
+]
+"#;
+        let files = vec![
+            "arrow".to_string(),
+            "close".to_string(),
+            "zebra".to_string(),
+        ];
+        let (updated, added) = update(manifest, &files)?;
+
+        assert_eq!(added, vec!["close".to_string()]);
+        assert_eq!(
+            updated,
+            r#"# icons of the app
+path = "downloads"
+files = [
+  "zebra", # the mascot
+  "arrow", # points left
+  "close",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn updates_should_add_the_files_if_missing() -> anyhow::Result<()> {
+        let (updated, added) = update("path = \"downloads\"\n", &["arrow".to_string()])?;
+
+        assert_eq!(added, vec!["arrow".to_string()]);
+        let parsed: Manifest = toml::from_str(&updated)?;
+        assert_eq!(parsed.files, vec!["arrow".to_string()]);
+        let (updated, _) = update("files = [\"arrow\"]\n", &["close".to_string()])?;
+        assert_eq!(updated, "files = [\"arrow\", \"close\"]\n");
+        assert!(update("files = \"arrow\"\n", &[]).is_err());
+        Ok(())
+    }
+}
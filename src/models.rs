@@ -1,6 +1,30 @@
 #![allow(clippy::non_ascii_literal)]
+use crate::{
+    app_icon::AppIcon,
+    collisions::CollisionStrategy,
+    converter::ConversionOptions,
+    jpg_optimizer::{parse_background, JpgOptions},
+    logger::LogFormat,
+    naming::NamingPolicy,
+    optimizer::OptimizationOptions,
+    path_template::PathTemplate,
+    png_quantizer::{QuantizationConfig, QuantizationOptions, QuantizationRule},
+    pruner::PruneMode,
+    report::OutputFormat,
+    resizer::SizeOutput,
+    snapshot,
+    svg_optimizer::SvgOptions,
+    watcher, webhook,
+};
+use log::LevelFilter;
 use serde::Deserialize;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use structopt::StructOpt;
 
 type ImageId = String;
@@ -11,6 +35,9 @@ const DEFAULT_PATH: &str = "downloads";
 const DEFAULT_FILE_EXT: &str = "png";
 const DEFAULT_FILE_SCALE: &str = "1";
 const DEFAULT_CONFIG_PATH: &str = "fad.toml";
+const DEFAULT_SVG_PRECISION: u8 = 3;
+const DEFAULT_VERSIONS_LIMIT: usize = 20;
+const DEFAULT_MANIFEST_PATH: &str = "fad_manifest.toml";
 
 #[derive(StructOpt, PartialEq, Debug, Deserialize)]
 #[structopt(
@@ -25,6 +52,12 @@ pub struct Cli {
     /// File id (www.figma.com/file/FILE_ID/title?node-id=DOCUMENT_ID)
     #[structopt(short, long)]
     pub file_id: Option<String>,
+    /// Version of the file to export, listed by the `versions` subcommand. Default: the latest one
+    #[structopt(long)]
+    pub file_version: Option<String>,
+    /// Reads the frames from a snapshot saved by the `snapshot` subcommand instead of the Figma API
+    #[structopt(long)]
+    pub snapshot: Option<String>,
     /// List of document ids (www.figma.com/file/FILE_ID/title?node-id=DOCUMENT_ID)
     #[structopt(short, long)]
     pub document_ids: Option<Vec<String>>,
@@ -40,10 +73,26 @@ pub struct Cli {
     #[structopt(short = "r", long)]
     #[serde(default = "default_force_file_extensions")]
     pub force_file_extensions: bool,
-    /// Scales to export to: 1, 2, 3, 4, default: 1
+    /// Scales to export to, from 0.01 to 4: 0.5, 1, 1.5, 2, 3, 4, default: 1
     #[structopt(short = "s", long, default_value = DEFAULT_FILE_SCALE)]
     #[serde(default = "default_scale")]
-    pub file_scales: Vec<usize>,
+    pub file_scales: Vec<Scale>,
+    /// Template of the paths of the images inside the download folder, default: "{scale_folder}/{name}.{ext}"
+    #[structopt(long)]
+    pub path_template: Option<String>,
+    /// What to do when several frames would be written to the same file: "warn", "error", "node-id", "counter", default: warn
+    #[structopt(long)]
+    pub on_collision: Option<String>,
+    /// Case of the file names: "snake", "kebab", "camel", "pascal", "lower", "upper", default: the name in Figma
+    #[structopt(long)]
+    pub name_case: Option<String>,
+    /// If true, non ascii characters of the file names are replaced with their ascii counterparts
+    #[structopt(long)]
+    #[serde(default = "default_name_transliterate")]
+    pub name_transliterate: bool,
+    /// Replacement of the characters that are not allowed in file names, default: "_"
+    #[structopt(long)]
+    pub name_replacement: Option<String>,
     /// Name of the figma-asset-downloader configuration
     #[structopt(short = "c", long, default_value = DEFAULT_CONFIG_PATH)]
     #[serde(default)]
@@ -51,22 +100,241 @@ pub struct Cli {
     /// Optimizes png images. You can set a level from 1 to 6. 2 to 4 recommended.
     #[structopt(long)]
     pub opt_png_level: Option<u8>,
+    /// If true, png images are quantized to a palette before being optimized. Lossy, but images shrink substantially.
+    #[structopt(long)]
+    #[serde(default = "default_png_quantize")]
+    pub png_quantize: bool,
+    /// Maximum number of colors of the quantized png images, from 2 to 256, default: 256
+    #[structopt(long)]
+    pub png_max_colors: Option<u16>,
+    /// Dithering level of the quantized png images, from 0.0 to 1.0, default: 1.0
+    #[structopt(long)]
+    pub png_dithering: Option<f32>,
+    /// Minimum quality of the quantized png images, from 0 to 100. Images below it are kept lossless.
+    #[structopt(long)]
+    pub png_min_quality: Option<u8>,
+    /// Quantization settings for the assets matching a pattern. Only available in the config file.
+    #[structopt(skip)]
+    pub png_quantization_rules: Option<Vec<QuantizationRule>>,
     /// Optimizes jpg images. You can set a level from 1 to 100. 80 recommended.
     #[structopt(long)]
     pub opt_jpg_level: Option<u8>,
+    /// Color used to flatten transparent images when optimizing jpg images, default: #ffffff
+    #[structopt(long)]
+    pub jpg_background: Option<String>,
+    /// If true, the icc profile and exif metadata are removed when optimizing jpg images
+    #[structopt(long)]
+    #[serde(default = "default_jpg_strip_metadata")]
+    pub jpg_strip_metadata: bool,
+    /// Optimizes svg images. You can set a level from 1 to 3. 1 cleans up, 2 collapses groups, 3 rounds numbers.
+    #[structopt(long)]
+    pub opt_svg_level: Option<u8>,
+    /// Number of decimals kept when optimizing svg images at level 3, default: 3
+    #[structopt(long)]
+    pub opt_svg_precision: Option<u8>,
+    /// Number of images optimized at the same time, default: number of cpus
+    #[structopt(long)]
+    pub opt_threads: Option<usize>,
+    /// Writes a json file with the size savings of every optimized image
+    #[structopt(long)]
+    pub opt_report: Option<String>,
     /// If true, only new added images will be optimized. It's useful to only apply optimization to recently imported images and not to all of them.
-    #[structopt(short = "v", long)]
+    #[structopt(long)]
     #[serde(default = "default_opt_only_on_validation")]
     pub opt_only_on_validation: bool,
+    /// If true, the files produced by previous runs that are no longer exported are pruned
+    #[structopt(long)]
+    #[serde(default = "default_prune")]
+    pub prune: bool,
+    /// How stale files are pruned: "delete", "trash", "list", default: delete
+    #[structopt(long)]
+    pub prune_mode: Option<String>,
+    /// Folder where the pruned files are moved in trash mode, default: .fad_trash
+    #[structopt(long)]
+    pub prune_trash_path: Option<String>,
     /// If true, it won't download the images that already exists in your download folder. Useful to avoid huge git diffs.
     #[structopt(short = "u", long)]
     #[serde(default = "default_download_only_unexisting_in_folder")]
     pub download_only_unexisting_in_folder: bool,
+    /// Formats derived locally from the downloaded png and jpg images: "webp", "avif"
+    #[structopt(long)]
+    pub derived_formats: Option<Vec<String>>,
+    /// Quality of the webp images from 0 to 100, default: lossless
+    #[structopt(long)]
+    pub webp_quality: Option<u8>,
+    /// Quality of the avif images from 0 to 100, default: 80
+    #[structopt(long)]
+    pub avif_quality: Option<u8>,
+    /// Extra outputs of exact pixel dimensions derived from the raster images. Only available in the config file.
+    #[structopt(skip)]
+    pub size_outputs: Option<Vec<SizeOutput>>,
+    /// Frames exported as the full set of favicons and app icons. Only available in the config file.
+    #[structopt(skip)]
+    pub app_icons: Option<Vec<AppIcon>>,
+    /// Languages to generate asset references for: "rust", "typescript", "dart", "swift", "kotlin"
+    #[structopt(long)]
+    pub codegen_languages: Option<Vec<String>>,
+    /// Folder where the generated asset references will be written, default: current folder
+    #[structopt(long)]
+    pub codegen_path: Option<String>,
+    /// If true, it prints what would be downloaded, overwritten or deleted without touching the file system
+    #[structopt(long)]
+    #[serde(default = "default_dry_run")]
+    pub dry_run: bool,
+    /// If true, the dry run also asks Figma to render the images, reporting the ones it can't render
+    #[structopt(long)]
+    #[serde(default = "default_resolve_urls")]
+    pub resolve_urls: bool,
+    /// Writes a json file with the dry run plan
+    #[structopt(long)]
+    pub plan_report: Option<String>,
+    /// Writes a json file with the report of the run: files, sizes, optimization results, errors and timing
+    #[structopt(long)]
+    pub report: Option<String>,
+    /// Format of the output: "human", "json", default: human. The json report is printed to stdout and the messages to stderr
+    #[structopt(long)]
+    pub output_format: Option<String>,
+    /// If true, only errors are printed
+    #[structopt(short = "q", long)]
+    #[serde(default = "default_quiet")]
+    pub quiet: bool,
+    /// Prints more details, like the Figma API urls. Use it twice to print everything
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    #[serde(default)]
+    pub verbose: u8,
+    /// Format of the messages: "human", "json", default: human
+    #[structopt(long)]
+    pub log_format: Option<String>,
     #[structopt(subcommand)]
     pub subcommands: Option<SubCommands>,
 }
 
 impl Cli {
+    /// Optimization levels of every format
+    pub fn optimization_options(&self) -> anyhow::Result<OptimizationOptions> {
+        let jpg_background = match &self.jpg_background {
+            Some(color) => Some(parse_background(color)?),
+            None => None,
+        };
+        let svg = match self.opt_svg_level {
+            Some(level @ 1..=3) => Some(SvgOptions::new(
+                level,
+                self.opt_svg_precision.unwrap_or(DEFAULT_SVG_PRECISION),
+            )),
+            Some(level) => {
+                return Err(anyhow::anyhow!(
+                    "Invalid svg optimization level {}. Use a level from 1 to 3",
+                    level
+                ))
+            }
+            None => None,
+        };
+        let defaults = QuantizationOptions::default();
+        let quantization_defaults = QuantizationOptions {
+            max_colors: self.png_max_colors.unwrap_or(defaults.max_colors),
+            dithering: self.png_dithering.unwrap_or(defaults.dithering),
+            min_quality: self.png_min_quality.unwrap_or(defaults.min_quality),
+        };
+        Ok(OptimizationOptions {
+            png_level: self.opt_png_level,
+            png_quantization: QuantizationConfig {
+                global: if self.png_quantize {
+                    Some(quantization_defaults)
+                } else {
+                    None
+                },
+                defaults: quantization_defaults,
+                rules: self.png_quantization_rules.clone().unwrap_or_default(),
+            },
+            jpg: self.opt_jpg_level.map(|quality| {
+                let options = JpgOptions::new(quality).with_strip_metadata(self.jpg_strip_metadata);
+                match jpg_background {
+                    Some(background) => options.with_background(background),
+                    None => options,
+                }
+            }),
+            svg,
+        })
+    }
+
+    /// Exact sizes written from the matching images. Fails if any of them is invalid.
+    pub fn size_outputs(&self) -> anyhow::Result<&[SizeOutput]> {
+        let outputs = self.size_outputs.as_deref().unwrap_or_default();
+        for output in outputs {
+            output.validate()?;
+        }
+        Ok(outputs)
+    }
+
+    /// Settings used to derive webp and avif images
+    pub const fn conversion_options(&self) -> ConversionOptions {
+        ConversionOptions {
+            webp_quality: self.webp_quality,
+            avif_quality: self.avif_quality,
+        }
+    }
+
+    /// Template of the paths of the images, the default layout if not set
+    pub fn path_template(&self) -> anyhow::Result<PathTemplate> {
+        match &self.path_template {
+            Some(template) => template.parse(),
+            None => Ok(PathTemplate::default()),
+        }
+    }
+
+    /// Strategy used when several frames would be written to the same file
+    pub fn collision_strategy(&self) -> anyhow::Result<CollisionStrategy> {
+        self.on_collision
+            .as_deref()
+            .map_or_else(|| Ok(CollisionStrategy::default()), str::parse)
+    }
+
+    /// Normalization applied to the names of the frames
+    pub fn naming_policy(&self) -> anyhow::Result<NamingPolicy> {
+        let defaults = NamingPolicy::default();
+        Ok(NamingPolicy {
+            case: self.name_case.as_deref().map(str::parse).transpose()?,
+            transliterate: self.name_transliterate,
+            replacement: self
+                .name_replacement
+                .clone()
+                .unwrap_or(defaults.replacement),
+        })
+    }
+
+    /// How stale files are pruned, None if pruning is disabled
+    pub fn prune_mode(&self) -> anyhow::Result<Option<PruneMode>> {
+        if !self.prune {
+            return Ok(None);
+        }
+        self.prune_mode
+            .as_deref()
+            .map_or_else(|| Ok(PruneMode::default()), str::parse)
+            .map(Some)
+    }
+
+    pub fn output_format(&self) -> anyhow::Result<OutputFormat> {
+        self.output_format
+            .as_deref()
+            .map_or_else(|| Ok(OutputFormat::default()), str::parse)
+    }
+
+    /// Level of the messages printed. Quiet wins over verbose.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+
+    pub fn log_format(&self) -> anyhow::Result<LogFormat> {
+        self.log_format
+            .as_deref()
+            .map_or_else(|| Ok(LogFormat::default()), str::parse)
+    }
+
     /// Adds non default values from another cli
     pub fn add_non_defaults(&mut self, other_cli: Self) {
         self.subcommands = other_cli.subcommands;
@@ -76,15 +344,51 @@ impl Cli {
         if other_cli.force_file_extensions {
             self.force_file_extensions = true;
         }
+        if other_cli.dry_run {
+            self.dry_run = true;
+        }
+        if other_cli.resolve_urls {
+            self.resolve_urls = true;
+        }
+        if other_cli.plan_report.is_some() {
+            self.plan_report = other_cli.plan_report;
+        }
+        if other_cli.report.is_some() {
+            self.report = other_cli.report;
+        }
+        if other_cli.output_format.is_some() {
+            self.output_format = other_cli.output_format;
+        }
+        if other_cli.quiet {
+            self.quiet = true;
+        }
+        if other_cli.verbose > 0 {
+            self.verbose = other_cli.verbose;
+        }
+        if other_cli.log_format.is_some() {
+            self.log_format = other_cli.log_format;
+        }
         if other_cli.download_only_unexisting_in_folder {
             self.download_only_unexisting_in_folder = true;
         }
+        if other_cli.jpg_strip_metadata {
+            self.jpg_strip_metadata = true;
+        }
+        if other_cli.png_quantize {
+            self.png_quantize = true;
+        }
         if other_cli.personal_access_token.is_some() {
             self.personal_access_token = other_cli.personal_access_token;
         }
         if other_cli.file_id.is_some() {
             self.file_id = other_cli.file_id;
         }
+        if other_cli.file_version.is_some() {
+            self.file_version = other_cli.file_version;
+        }
+        if other_cli.snapshot.is_some() {
+            self.snapshot = other_cli.snapshot;
+        }
         if other_cli.document_ids.is_some() {
             self.document_ids = other_cli.document_ids;
         }
@@ -94,6 +398,69 @@ impl Cli {
         if other_cli.opt_jpg_level.is_some() {
             self.opt_jpg_level = other_cli.opt_jpg_level;
         }
+        if other_cli.png_max_colors.is_some() {
+            self.png_max_colors = other_cli.png_max_colors;
+        }
+        if other_cli.png_dithering.is_some() {
+            self.png_dithering = other_cli.png_dithering;
+        }
+        if other_cli.png_min_quality.is_some() {
+            self.png_min_quality = other_cli.png_min_quality;
+        }
+        if other_cli.jpg_background.is_some() {
+            self.jpg_background = other_cli.jpg_background;
+        }
+        if other_cli.opt_svg_level.is_some() {
+            self.opt_svg_level = other_cli.opt_svg_level;
+        }
+        if other_cli.opt_svg_precision.is_some() {
+            self.opt_svg_precision = other_cli.opt_svg_precision;
+        }
+        if other_cli.opt_threads.is_some() {
+            self.opt_threads = other_cli.opt_threads;
+        }
+        if other_cli.opt_report.is_some() {
+            self.opt_report = other_cli.opt_report;
+        }
+        if other_cli.derived_formats.is_some() {
+            self.derived_formats = other_cli.derived_formats;
+        }
+        if other_cli.webp_quality.is_some() {
+            self.webp_quality = other_cli.webp_quality;
+        }
+        if other_cli.avif_quality.is_some() {
+            self.avif_quality = other_cli.avif_quality;
+        }
+        if other_cli.codegen_languages.is_some() {
+            self.codegen_languages = other_cli.codegen_languages;
+        }
+        if other_cli.codegen_path.is_some() {
+            self.codegen_path = other_cli.codegen_path;
+        }
+        if other_cli.path_template.is_some() {
+            self.path_template = other_cli.path_template;
+        }
+        if other_cli.on_collision.is_some() {
+            self.on_collision = other_cli.on_collision;
+        }
+        if other_cli.prune {
+            self.prune = true;
+        }
+        if other_cli.prune_mode.is_some() {
+            self.prune_mode = other_cli.prune_mode;
+        }
+        if other_cli.prune_trash_path.is_some() {
+            self.prune_trash_path = other_cli.prune_trash_path;
+        }
+        if other_cli.name_case.is_some() {
+            self.name_case = other_cli.name_case;
+        }
+        if other_cli.name_transliterate {
+            self.name_transliterate = true;
+        }
+        if other_cli.name_replacement.is_some() {
+            self.name_replacement = other_cli.name_replacement;
+        }
         if other_cli.path != default_path() {
             self.path = other_cli.path;
         }
@@ -112,7 +479,7 @@ impl Cli {
         if other_cli
             .file_scales
             .iter()
-            .filter(|&&x| x != DEFAULT_FILE_SCALE.parse::<usize>().unwrap())
+            .filter(|&&x| x != Scale::ONE)
             .count()
             > 0
         {
@@ -128,6 +495,73 @@ pub enum SubCommands {
         #[structopt(short = "p", long, default_value = "fad_manifest.toml")]
         #[serde(default)]
         path: String,
+        /// Compares the manifest with the frames of the Figma file instead of the download folder
+        #[structopt(long)]
+        #[serde(default)]
+        remote: bool,
+    },
+    #[structopt(
+        about = "Polls the Figma file and syncs the assets every time a new version is saved"
+    )]
+    Watch {
+        /// Seconds between every check of the file version
+        #[structopt(long, default_value = "60")]
+        #[serde(default = "default_watch_interval")]
+        interval: u64,
+        /// Seconds a new version must stay unchanged before syncing, so designers can keep editing
+        #[structopt(long, default_value = "10")]
+        #[serde(default = "default_watch_debounce")]
+        debounce: u64,
+    },
+    #[structopt(about = "Generates or updates the manifest with the frames found in Figma")]
+    Manifest(ManifestCommand),
+    #[structopt(about = "Saves the nodes of the documents to export them later without network")]
+    Snapshot {
+        #[structopt(short = "p", long, default_value = "fad_snapshot.json")]
+        #[serde(default = "default_snapshot_path")]
+        path: String,
+    },
+    #[structopt(about = "Lists the recent versions of the Figma file")]
+    Versions {
+        /// Number of versions to list, up to 50
+        #[structopt(long, default_value = "20")]
+        #[serde(default = "default_versions_limit")]
+        limit: usize,
+    },
+    #[structopt(
+        about = "Listens to Figma webhooks and syncs the assets every time the file is updated"
+    )]
+    Serve {
+        /// Address the server listens on
+        #[structopt(long, default_value = "127.0.0.1")]
+        #[serde(default = "default_serve_host")]
+        host: String,
+        #[structopt(long, default_value = "4000")]
+        #[serde(default = "default_serve_port")]
+        port: u16,
+        /// Passcode of the Figma webhook. Events with a different one are rejected
+        #[structopt(long)]
+        passcode: String,
+    },
+}
+
+#[derive(StructOpt, Debug, PartialEq, Deserialize)]
+pub enum ManifestCommand {
+    #[structopt(about = "Writes a manifest with the files that would be exported")]
+    Generate {
+        #[structopt(short = "p", long, default_value = DEFAULT_MANIFEST_PATH)]
+        #[serde(default = "default_manifest_path")]
+        path: String,
+        /// Overwrites the manifest if it already exists
+        #[structopt(long)]
+        #[serde(default)]
+        force: bool,
+    },
+    #[structopt(about = "Adds the new files to the manifest, keeping its comments and order")]
+    Update {
+        #[structopt(short = "p", long, default_value = DEFAULT_MANIFEST_PATH)]
+        #[serde(default = "default_manifest_path")]
+        path: String,
     },
 }
 
@@ -138,9 +572,9 @@ pub struct Manifest {
     /// Extensions to export to in case there's no extension in the name of the asset: "png", "svg", "jpg", default: png
     #[serde(default = "default_format")]
     pub file_extensions: Vec<String>,
-    /// Scales to export to: 1, 2, 3, 4, default: 1
+    /// Scales to export to, from 0.01 to 4: 0.5, 1, 1.5, 2, 3, 4, default: 1
     #[serde(default = "default_scale")]
-    pub file_scales: Vec<usize>,
+    pub file_scales: Vec<Scale>,
     /// Path where assets will be downloaded
     #[serde(default = "default_path")]
     pub path: String,
@@ -166,8 +600,8 @@ impl Manifest {
 }
 
 // methods below have been implemented for default values when using fad.toml
-fn default_scale() -> Vec<usize> {
-    vec![1]
+fn default_scale() -> Vec<Scale> {
+    vec![Scale::ONE]
 }
 
 fn default_format() -> Vec<String> {
@@ -186,17 +620,160 @@ const fn default_opt_only_on_validation() -> bool {
     false
 }
 
+const fn default_quiet() -> bool {
+    false
+}
+
+const fn default_dry_run() -> bool {
+    false
+}
+
+const fn default_resolve_urls() -> bool {
+    false
+}
+
 const fn default_download_only_unexisting_in_folder() -> bool {
     false
 }
+
+const fn default_png_quantize() -> bool {
+    false
+}
+
+const fn default_prune() -> bool {
+    false
+}
+
+const fn default_name_transliterate() -> bool {
+    false
+}
+
+const fn default_jpg_strip_metadata() -> bool {
+    false
+}
+
+const fn default_watch_interval() -> u64 {
+    watcher::DEFAULT_INTERVAL_SECS
+}
+
+const fn default_watch_debounce() -> u64 {
+    watcher::DEFAULT_DEBOUNCE_SECS
+}
+
+fn default_manifest_path() -> String {
+    DEFAULT_MANIFEST_PATH.to_string()
+}
+
+fn default_snapshot_path() -> String {
+    snapshot::DEFAULT_SNAPSHOT_PATH.to_string()
+}
+
+const fn default_versions_limit() -> usize {
+    DEFAULT_VERSIONS_LIMIT
+}
+
+fn default_serve_host() -> String {
+    webhook::DEFAULT_HOST.to_string()
+}
+
+const fn default_serve_port() -> u16 {
+    webhook::DEFAULT_PORT
+}
+
 // end of default values for serde
 
+/// Figma file the assets are exported from, pinned to a version if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSource<'a> {
+    pub id: &'a str,
+    pub version: Option<&'a str>,
+}
+
+impl FileSource<'_> {
+    pub fn nodes_url(&self, document_id: &str) -> String {
+        format!(
+            "https://api.figma.com/v1/files/{}/nodes?ids={}{}",
+            self.id,
+            document_id,
+            self.version_query()
+        )
+    }
+
+    pub fn images_url(&self, image_ids: &str, scale: Scale, format: &str) -> String {
+        format!(
+            "https://api.figma.com/v1/images/{}?ids={}&scale={}&format={}{}",
+            self.id,
+            image_ids,
+            scale,
+            format,
+            self.version_query()
+        )
+    }
+
+    fn version_query(&self) -> String {
+        self.version
+            .map(|version| format!("&version={}", version))
+            .unwrap_or_default()
+    }
+}
+
+/// Versions of a Figma file, most recent first
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FileVersions {
+    pub versions: Vec<FileVersion>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FileVersion {
+    pub id: String,
+    pub created_at: String,
+    /// Only the named versions have a label, autosaved ones don't
+    pub label: Option<String>,
+    pub user: FigmaUser,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FigmaUser {
+    pub handle: String,
+}
+
+/// Metadata of a Figma file, returned by the files endpoint
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    pub name: String,
+    pub version: String,
+    pub last_modified: String,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Page {
+    #[allow(dead_code)]
     pub name: String,
     pub nodes: HashMap<String, Document>,
 }
 
+impl Page {
+    /// Frames of the document, with the name of their page
+    pub fn into_frames(mut self, document_id: &str) -> Frames {
+        let document = match self.nodes.remove(document_id) {
+            Some(doc) => doc.document,
+            None => return vec![],
+        };
+        let page_name = document.name;
+        document
+            .children
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|node| node.node_type == NodeType::FRAME)
+            .map(|mut node| {
+                node.page_name = Some(page_name.clone());
+                node
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Document {
     pub document: Node,
@@ -229,6 +806,17 @@ pub struct Node {
     #[serde(rename = "type")]
     pub node_type: NodeType,
     pub children: Option<Vec<Node>>,
+    #[serde(rename = "absoluteBoundingBox")]
+    pub absolute_bounding_box: Option<BoundingBox>,
+    /// Name of the page containing the node, set when getting the frames
+    #[serde(skip)]
+    pub page_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub width: f64,
+    pub height: f64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -236,32 +824,132 @@ pub struct ImageUrlCollection {
     pub images: HashMap<ImageId, ImageUrl>,
 }
 
+#[derive(Debug, Clone)]
 pub struct Image {
     pub id: String,
     pub name: String,
-    pub scale: usize,
+    pub scale: Scale,
     pub format: String,
     pub url: String,
+    /// Name of the Figma page containing the frame
+    pub page: Option<String>,
 }
 
 impl Image {
-    pub fn new(id: String, name: &str, scale: usize, format: String, url: String) -> Self {
+    pub fn new(id: String, name: &str, scale: Scale, format: String, url: String) -> Self {
         Self {
             id,
             name: remove_extension(name),
             scale,
             format,
             url,
+            page: None,
+        }
+    }
+
+    pub fn with_page(mut self, page: Option<String>) -> Self {
+        self.page = page;
+        self
+    }
+
+    /// Path of the image relative to the download folder
+    pub fn relative_path(&self, template: &PathTemplate) -> PathBuf {
+        template.render(self)
+    }
+}
+
+/// Export scale. Figma renders images from 0.01x to 4x.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "f32")]
+pub struct Scale(f32);
+
+impl Scale {
+    pub const ONE: Self = Self(1.0);
+    pub const MIN: f32 = 0.01;
+    pub const MAX: f32 = 4.0;
+
+    pub fn new(value: f32) -> anyhow::Result<Self> {
+        if (Self::MIN..=Self::MAX).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(anyhow::anyhow!(
+                "Invalid scale {}. Figma only accepts scales from {} to {}",
+                value,
+                Self::MIN,
+                Self::MAX
+            ))
+        }
+    }
+
+    pub const fn value(self) -> f32 {
+        self.0
+    }
+
+    /// Folder of the images of this scale: `2.0x`, `1.5x`, `0.25x`... None for 1x images.
+    pub fn folder(self) -> Option<String> {
+        if self == Self::ONE {
+            None
+        } else if self.0.fract() == 0.0 {
+            Some(format!("{:.1}x", self.0))
+        } else {
+            Some(format!("{}x", self.0))
         }
     }
 }
 
+impl TryFrom<f32> for Scale {
+    type Error = anyhow::Error;
+
+    fn try_from(value: f32) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl FromStr for Scale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s
+            .trim()
+            .trim_end_matches('x')
+            .parse::<f32>()
+            .map_err(|_| anyhow::anyhow!("Invalid scale {}. Use numbers like 1 or 1.5", s))?;
+        Self::new(value)
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// scales are always finite numbers so they can be totally ordered
+impl Eq for Scale {}
+
+impl Ord for Scale {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Scale {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Removes the extension of the last segment, keeping the slash separated segments of the name
 fn remove_extension(filename: &str) -> String {
-    Path::new(filename)
+    let (folders, file) = match filename.rfind('/') {
+        Some(i) => filename.split_at(i + 1),
+        None => ("", filename),
+    };
+    let stem = Path::new(file)
         .file_stem()
         .and_then(std::ffi::OsStr::to_str)
-        .expect("Some unexpected error happened removing the extension of an image")
-        .to_string()
+        .unwrap_or(file);
+    format!("{}{}", folders, stem)
 }
 
 #[cfg(test)]
@@ -272,16 +960,52 @@ mod tests {
         Cli {
             personal_access_token: None,
             file_id: None,
+            file_version: None,
+            snapshot: None,
             document_ids: None,
             path: "".to_string(),
-            file_scales: vec![1],
+            file_scales: vec![Scale::ONE],
+            path_template: None,
+            on_collision: None,
+            name_case: None,
+            name_transliterate: false,
+            name_replacement: None,
             file_extensions: vec![DEFAULT_FILE_EXT.to_owned()],
             force_file_extensions: false,
             download_only_unexisting_in_folder: false,
             config_path: "".to_string(),
             opt_png_level: None,
+            png_quantize: false,
+            png_max_colors: None,
+            png_dithering: None,
+            png_min_quality: None,
+            png_quantization_rules: None,
             opt_jpg_level: None,
+            jpg_background: None,
+            jpg_strip_metadata: false,
+            opt_svg_level: None,
+            opt_svg_precision: None,
+            opt_threads: None,
+            opt_report: None,
             opt_only_on_validation: false,
+            prune: false,
+            prune_mode: None,
+            prune_trash_path: None,
+            derived_formats: None,
+            webp_quality: None,
+            avif_quality: None,
+            size_outputs: None,
+            app_icons: None,
+            codegen_languages: None,
+            codegen_path: None,
+            dry_run: false,
+            resolve_urls: false,
+            plan_report: None,
+            report: None,
+            output_format: None,
+            quiet: false,
+            verbose: 0,
+            log_format: None,
             subcommands: None,
         }
     }
@@ -293,6 +1017,7 @@ mod tests {
 
         other.subcommands = Some(SubCommands::ValidateManifest {
             path: "a".to_string(),
+            remote: false,
         });
 
         cli.add_non_defaults(other);
@@ -302,6 +1027,7 @@ mod tests {
             cli.subcommands,
             Some(SubCommands::ValidateManifest {
                 path: "a".to_string(),
+                remote: false,
             })
         );
     }
@@ -345,6 +1071,67 @@ mod tests {
         assert!(cli.download_only_unexisting_in_folder);
     }
 
+    #[test]
+    fn cli_add_non_defaults_add_dry_run_if_not_default() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.dry_run = true;
+        other.resolve_urls = true;
+        other.plan_report = Some("plan.json".to_string());
+        cli.add_non_defaults(other);
+
+        assert!(cli.dry_run);
+        assert!(cli.resolve_urls);
+        assert_eq!(cli.plan_report, Some("plan.json".to_string()));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_report_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert_eq!(cli.output_format().unwrap(), OutputFormat::Human);
+        other.report = Some("report.json".to_string());
+        other.output_format = Some("json".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.report, Some("report.json".to_string()));
+        assert_eq!(cli.output_format().unwrap(), OutputFormat::Json);
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_logging_if_not_default() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert_eq!(cli.log_level(), LevelFilter::Info);
+        other.verbose = 2;
+        other.log_format = Some("json".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.log_level(), LevelFilter::Trace);
+        assert_eq!(cli.log_format().unwrap(), LogFormat::Json);
+
+        let mut other = build_default_cli();
+        other.quiet = true;
+        cli.add_non_defaults(other);
+        assert_eq!(cli.log_level(), LevelFilter::Error);
+    }
+
+    #[test]
+    fn cli_verbosity_should_be_read_from_the_short_flag() {
+        let cli = Cli::from_iter(&["fad", "-vv"]);
+
+        assert_eq!(cli.verbose, 2);
+        assert!(!cli.opt_only_on_validation);
+        assert_eq!(cli.log_level(), LevelFilter::Trace);
+        assert_eq!(
+            Cli::from_iter(&["fad", "-v"]).log_level(),
+            LevelFilter::Debug
+        );
+    }
+
     #[test]
     fn cli_add_non_defaults_add_pat_if_some() {
         let mut cli = build_default_cli();
@@ -405,6 +1192,372 @@ mod tests {
         assert_eq!(cli.opt_jpg_level, Some(10));
     }
 
+    #[test]
+    fn cli_add_non_defaults_add_png_quantize_if_true() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert!(!cli.png_quantize);
+
+        other.png_quantize = true;
+        cli.add_non_defaults(other);
+
+        assert!(cli.png_quantize);
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_png_quantization_settings_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.png_max_colors = Some(64);
+        other.png_dithering = Some(0.5);
+        other.png_min_quality = Some(70);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.png_max_colors, Some(64));
+        assert_eq!(cli.png_dithering, Some(0.5));
+        assert_eq!(cli.png_min_quality, Some(70));
+    }
+
+    #[test]
+    fn cli_png_quantization_rules_are_read_from_the_config_file() {
+        let cli: Cli = toml::from_str(
+            r#"
+            png_quantize = false
+            [[png_quantization_rules]]
+            pattern = "illustration*"
+            max_colors = 128
+            "#,
+        )
+        .unwrap();
+        let options = cli.optimization_options().unwrap();
+
+        assert!(options.png_quantization.global.is_none());
+        assert_eq!(
+            options
+                .png_quantization
+                .options_for(Path::new("illustration_home.png"))
+                .map(|o| o.max_colors),
+            Some(128)
+        );
+    }
+
+    #[test]
+    fn cli_size_outputs_are_read_from_the_config_file() {
+        let cli: Cli = toml::from_str(
+            r#"
+            [[size_outputs]]
+            pattern = "app_icon"
+            width = 180
+            height = 180
+            mode = "fill"
+            trim = true
+            "#,
+        )
+        .unwrap();
+        let outputs = cli.size_outputs.unwrap();
+
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].mode, crate::resizer::ResizeMode::Fill);
+        assert_eq!(outputs[0].suffix(), "_180x180");
+        assert_eq!(outputs[0].padding, 0);
+    }
+
+    #[test]
+    fn cli_size_outputs_should_fail_with_an_invalid_output() {
+        let cli: Cli = toml::from_str(
+            r#"
+            [[size_outputs]]
+            pattern = "app_icon"
+            width = 180
+            [[size_outputs]]
+            pattern = "logo"
+            "#,
+        )
+        .unwrap();
+
+        assert!(cli.size_outputs().is_err());
+    }
+
+    #[test]
+    fn cli_app_icons_are_read_from_the_config_file() {
+        let cli: Cli = toml::from_str(
+            r##"
+            [[app_icons]]
+            name = "app_icon"
+            background = "#101010"
+            "##,
+        )
+        .unwrap();
+        let icons = cli.app_icons.unwrap();
+
+        assert_eq!(icons[0].name, "app_icon");
+        assert_eq!(
+            icons[0].folder(Path::new("downloads")),
+            PathBuf::from("downloads/app_icon")
+        );
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_jpg_background_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.jpg_background = Some("#000000".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.jpg_background, Some("#000000".to_string()));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_jpg_strip_metadata_if_true() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert!(!cli.jpg_strip_metadata);
+
+        other.jpg_strip_metadata = true;
+        cli.add_non_defaults(other);
+
+        assert!(cli.jpg_strip_metadata);
+    }
+
+    #[test]
+    fn cli_optimization_options_should_fail_with_invalid_jpg_background() {
+        let mut cli = build_default_cli();
+        cli.opt_jpg_level = Some(80);
+        cli.jpg_background = Some("white".to_string());
+
+        assert!(cli.optimization_options().is_err());
+    }
+
+    #[test]
+    fn cli_optimization_options_should_fail_with_invalid_svg_level() {
+        let mut cli = build_default_cli();
+        for level in &[1, 3] {
+            cli.opt_svg_level = Some(*level);
+            assert!(cli.optimization_options().is_ok());
+        }
+        for level in &[0, 4] {
+            cli.opt_svg_level = Some(*level);
+            assert!(cli.optimization_options().is_err());
+        }
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_opt_svg_level_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.opt_svg_level = Some(2);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.opt_svg_level, Some(2));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_opt_svg_precision_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.opt_svg_precision = Some(2);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.opt_svg_precision, Some(2));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_opt_threads_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.opt_threads = Some(4);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.opt_threads, Some(4));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_opt_report_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.opt_report = Some("x.json".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.opt_report, Some("x.json".to_string()));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_derived_formats_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.derived_formats = Some(vec!["webp".to_string()]);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.derived_formats, Some(vec!["webp".to_string()]));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_webp_quality_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.webp_quality = Some(80);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.webp_quality, Some(80));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_avif_quality_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.avif_quality = Some(60);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.avif_quality, Some(60));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_codegen_languages_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.codegen_languages = Some(vec!["rust".to_string()]);
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.codegen_languages, Some(vec!["rust".to_string()]));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_codegen_path_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.codegen_path = Some("x".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.codegen_path, Some("x".to_string()));
+    }
+
+    #[test]
+    fn image_relative_path_uses_scale_folder() {
+        let image = |scale: f32| {
+            let scale = Scale::new(scale).unwrap();
+            Image::new("1:1".into(), "a", scale, "png".into(), "".into())
+        };
+        let template = PathTemplate::default();
+
+        assert_eq!(image(1.0).relative_path(&template), PathBuf::from("a.png"));
+        assert_eq!(
+            image(2.0).relative_path(&template),
+            Path::new("2.0x").join("a.png")
+        );
+        assert_eq!(
+            image(1.5).relative_path(&template),
+            Path::new("1.5x").join("a.png")
+        );
+        assert_eq!(
+            image(0.25).relative_path(&template),
+            Path::new("0.25x").join("a.png")
+        );
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_on_collision_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert_eq!(cli.collision_strategy().unwrap(), CollisionStrategy::Warn);
+        other.on_collision = Some("counter".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(
+            cli.collision_strategy().unwrap(),
+            CollisionStrategy::Counter
+        );
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_naming_policy_if_not_default() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert_eq!(cli.naming_policy().unwrap(), NamingPolicy::default());
+        other.name_case = Some("kebab".to_string());
+        other.name_transliterate = true;
+        other.name_replacement = Some("-".to_string());
+        cli.add_non_defaults(other);
+
+        let policy = cli.naming_policy().unwrap();
+        assert_eq!(policy.case, Some(crate::naming::Case::Kebab));
+        assert!(policy.transliterate);
+        assert_eq!(policy.replacement, "-");
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_prune_if_not_default() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        assert_eq!(cli.prune_mode().unwrap(), None);
+        other.prune = true;
+        other.prune_mode = Some("trash".to_string());
+        other.prune_trash_path = Some("x".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.prune_mode().unwrap(), Some(PruneMode::Trash));
+        assert_eq!(cli.prune_trash_path, Some("x".to_string()));
+    }
+
+    #[test]
+    fn cli_add_non_defaults_add_path_template_if_some() {
+        let mut cli = build_default_cli();
+        let mut other = build_default_cli();
+
+        other.path_template = Some("{format}/{name}.{ext}".to_string());
+        cli.add_non_defaults(other);
+
+        assert_eq!(cli.path_template, Some("{format}/{name}.{ext}".to_string()));
+        assert!(cli.path_template().is_ok());
+    }
+
+    #[test]
+    fn image_name_keeps_its_segments() {
+        let image = Image::new(
+            "1:1".into(),
+            "icons/arrow.png",
+            Scale::ONE,
+            "png".into(),
+            "".into(),
+        );
+        assert_eq!(image.name, "icons/arrow");
+    }
+
+    #[test]
+    fn scales_should_be_in_the_figma_range() {
+        assert_eq!("1.5".parse::<Scale>().unwrap().value(), 1.5);
+        assert_eq!("2x".parse::<Scale>().unwrap().value(), 2.0);
+        assert_eq!("0.01".parse::<Scale>().unwrap().value(), 0.01);
+        assert!("0".parse::<Scale>().is_err());
+        assert!("4.5".parse::<Scale>().is_err());
+        assert!("big".parse::<Scale>().is_err());
+    }
+
+    #[test]
+    fn cli_fractional_scales_are_read_from_the_config_file() {
+        let cli: Cli = toml::from_str("file_scales = [0.5, 1.5]").unwrap();
+        assert_eq!(
+            cli.file_scales,
+            vec![Scale::new(0.5).unwrap(), Scale::new(1.5).unwrap()]
+        );
+        assert!(toml::from_str::<Cli>("file_scales = [5]").is_err());
+    }
+
     #[test]
     fn cli_add_non_defaults_add_path_if_not_default() {
         let mut cli = build_default_cli();
@@ -445,9 +1598,127 @@ mod tests {
         let mut cli = build_default_cli();
         let mut other = build_default_cli();
 
-        other.file_scales = vec![1, 2, 3];
+        let scales = vec![
+            Scale::ONE,
+            Scale::new(1.5).unwrap(),
+            Scale::new(3.0).unwrap(),
+        ];
+        other.file_scales = scales.clone();
         cli.add_non_defaults(other);
 
-        assert_eq!(cli.file_scales, vec![1, 2, 3]);
+        assert_eq!(cli.file_scales, scales);
+    }
+
+    #[test]
+    fn watch_subcommand_should_have_defaults() {
+        let cli = Cli::from_iter(&["fad", "watch", "--interval", "30"]);
+
+        assert_eq!(
+            cli.subcommands,
+            Some(SubCommands::Watch {
+                interval: 30,
+                debounce: watcher::DEFAULT_DEBOUNCE_SECS,
+            })
+        );
+    }
+
+    #[test]
+    fn manifest_subcommands_should_be_parsed() {
+        let cli = Cli::from_iter(&["fad", "manifest", "generate", "--force"]);
+        assert_eq!(
+            cli.subcommands,
+            Some(SubCommands::Manifest(ManifestCommand::Generate {
+                path: DEFAULT_MANIFEST_PATH.to_string(),
+                force: true,
+            }))
+        );
+
+        let cli = Cli::from_iter(&["fad", "manifest", "update", "-p", "icons.toml"]);
+        assert_eq!(
+            cli.subcommands,
+            Some(SubCommands::Manifest(ManifestCommand::Update {
+                path: "icons.toml".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn serve_subcommand_should_require_the_passcode() {
+        let cli = Cli::from_iter(&["fad", "serve", "--passcode", "secret"]);
+
+        assert_eq!(
+            cli.subcommands,
+            Some(SubCommands::Serve {
+                host: webhook::DEFAULT_HOST.to_string(),
+                port: webhook::DEFAULT_PORT,
+                passcode: "secret".to_string(),
+            })
+        );
+        assert!(Cli::from_iter_safe(&["fad", "serve"]).is_err());
+    }
+
+    #[test]
+    fn file_info_should_be_deserialized() {
+        let info: FileInfo = serde_json::from_str(
+            r#"{"name":"Icons","version":"123","lastModified":"2021-01-01T00:00:00Z","document":{}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(info.version, "123");
+        assert_eq!(info.last_modified, "2021-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn file_source_urls_should_contain_the_version() {
+        let latest = FileSource {
+            id: "abc",
+            version: None,
+        };
+        let pinned = FileSource {
+            version: Some("123"),
+            ..latest
+        };
+
+        assert_eq!(
+            latest.nodes_url("1:2"),
+            "https://api.figma.com/v1/files/abc/nodes?ids=1:2"
+        );
+        assert_eq!(
+            pinned.nodes_url("1:2"),
+            "https://api.figma.com/v1/files/abc/nodes?ids=1:2&version=123"
+        );
+        assert_eq!(
+            pinned.images_url("1:2,1:3", Scale::new(2.0).unwrap(), "svg"),
+            "https://api.figma.com/v1/images/abc?ids=1:2,1:3&scale=2&format=svg&version=123"
+        );
+    }
+
+    #[test]
+    fn file_versions_should_be_deserialized() {
+        let file_versions: FileVersions = serde_json::from_str(
+            r#"{
+                "versions": [
+                    {"id":"2","created_at":"2021-02-01T00:00:00Z","label":"Release 2","description":"","user":{"id":"1","handle":"Ana","img_url":""}},
+                    {"id":"1","created_at":"2021-01-01T00:00:00Z","label":null,"description":null,"user":{"id":"1","handle":"Ana","img_url":""}}
+                ],
+                "pagination": {}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(file_versions.versions.len(), 2);
+        assert_eq!(
+            file_versions.versions[0].label.as_deref(),
+            Some("Release 2")
+        );
+        assert_eq!(file_versions.versions[1].label, None);
+        assert_eq!(file_versions.versions[1].user.handle, "Ana");
+    }
+
+    #[test]
+    fn file_version_should_be_read_from_the_config_file() {
+        let cli: Cli = toml::from_str(r#"file_version = "123""#).unwrap();
+
+        assert_eq!(cli.file_version.as_deref(), Some("123"));
     }
 }
@@ -0,0 +1,315 @@
+use std::str::FromStr;
+
+/// Case transformations applied to the names of the assets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Lower,
+    Upper,
+    Snake,
+    Kebab,
+    Camel,
+    Pascal,
+}
+
+impl FromStr for Case {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lower" | "lowercase" => Ok(Self::Lower),
+            "upper" | "uppercase" => Ok(Self::Upper),
+            "snake" | "snake_case" => Ok(Self::Snake),
+            "kebab" | "kebab-case" => Ok(Self::Kebab),
+            "camel" | "camelcase" => Ok(Self::Camel),
+            "pascal" | "pascalcase" => Ok(Self::Pascal),
+            _ => Err(anyhow::anyhow!(
+                "Unknown case {}. Use lower, upper, snake, kebab, camel or pascal",
+                s
+            )),
+        }
+    }
+}
+
+impl Case {
+    pub fn apply(self, value: &str) -> String {
+        match self {
+            Self::Lower => value.to_lowercase(),
+            Self::Upper => value.to_uppercase(),
+            Self::Snake => split_words(value).join("_"),
+            Self::Kebab => split_words(value).join("-"),
+            Self::Camel => split_words(value)
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect(),
+            Self::Pascal => split_words(value).iter().map(|w| capitalize(w)).collect(),
+        }
+    }
+}
+
+const DEFAULT_REPLACEMENT: &str = "_";
+/// Characters that are not allowed in file names in some operating systems
+const FORBIDDEN_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Makes a single folder or file name safe for every file system.
+/// Returns None for the segments that must be skipped, like `..`, so paths can't escape the download folder.
+pub fn sanitize_segment(segment: &str) -> Option<String> {
+    let sanitized = segment
+        .chars()
+        .map(|c| {
+            if FORBIDDEN_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    // windows doesn't allow trailing dots or spaces
+    let sanitized = sanitized.trim().trim_end_matches('.').trim_end();
+    if sanitized.is_empty() {
+        return None;
+    }
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.contains(&stem.to_lowercase().as_str()) {
+        Some(format!("_{}", sanitized))
+    } else {
+        Some(sanitized.to_string())
+    }
+}
+
+/// Normalization applied to the names of the frames before using them as file names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamingPolicy {
+    pub case: Option<Case>,
+    /// Replaces the non ascii characters with their ascii counterparts, like `é` with `e`
+    pub transliterate: bool,
+    /// Used instead of the characters that are not allowed in file names
+    pub replacement: String,
+}
+
+impl Default for NamingPolicy {
+    fn default() -> Self {
+        Self {
+            case: None,
+            transliterate: false,
+            replacement: DEFAULT_REPLACEMENT.to_string(),
+        }
+    }
+}
+
+impl NamingPolicy {
+    /// Applies the policy to every slash separated segment of the name.
+    /// Segments that would end up empty are kept as they were.
+    pub fn apply(&self, name: &str) -> String {
+        name.split('/')
+            .map(|segment| {
+                let normalized = self.apply_to_segment(segment);
+                if normalized.trim().is_empty() {
+                    segment.to_string()
+                } else {
+                    normalized
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Applies the policy to a file name, keeping its extension
+    pub fn apply_to_file_name(&self, file_name: &str) -> String {
+        let (folders, file) = match file_name.rfind('/') {
+            Some(i) => file_name.split_at(i + 1),
+            None => ("", file_name),
+        };
+        match file.rfind('.') {
+            Some(i) if i > 0 => {
+                let (stem, extension) = file.split_at(i);
+                format!(
+                    "{}{}",
+                    self.apply(&format!("{}{}", folders, stem)),
+                    extension
+                )
+            }
+            _ => self.apply(file_name),
+        }
+    }
+
+    fn apply_to_segment(&self, segment: &str) -> String {
+        let mut normalized = String::with_capacity(segment.len());
+        for c in segment.chars() {
+            if FORBIDDEN_CHARS.contains(&c) || c.is_control() {
+                normalized.push_str(&self.replacement);
+            } else if self.transliterate && !c.is_ascii() {
+                match transliterate(c) {
+                    Some(ascii) => normalized.push_str(ascii),
+                    None => normalized.push_str(&self.replacement),
+                }
+            } else {
+                normalized.push(c);
+            }
+        }
+        match self.case {
+            Some(case) => case.apply(&normalized),
+            None => normalized,
+        }
+    }
+}
+
+/// Ascii counterpart of the most common latin characters
+pub fn transliterate(c: char) -> Option<&'static str> {
+    let ascii = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'ď' | 'đ' => "d",
+        'Ď' | 'Đ' => "D",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ě' | 'ę' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ě' | 'Ę' => "E",
+        'ğ' => "g",
+        'Ğ' => "G",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' | 'İ' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ñ' | 'ń' | 'ň' => "n",
+        'Ñ' | 'Ń' | 'Ň' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ř' => "r",
+        'Ř' => "R",
+        'ś' | 'š' | 'ş' => "s",
+        'Ś' | 'Š' | 'Ş' => "S",
+        'ß' => "ss",
+        'ť' | 'ţ' => "t",
+        'Ť' | 'Ţ' => "T",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+/// Splits a name into lowercase words, taking into account separators and camelCase.
+/// Non ascii letters, like `é` or `ñ`, are part of the words.
+pub fn split_words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut previous: Option<char> = None;
+
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            let is_boundary =
+                previous.is_some_and(|p| (p.is_lowercase() || p.is_numeric()) && c.is_uppercase());
+            if is_boundary && !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            current.extend(c.to_lowercase());
+            previous = Some(c);
+        } else {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            previous = None;
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().collect::<String>() + chars.as_str()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cases_should_be_applied() {
+        let name = "Arrow leftIcon-24";
+        assert_eq!(Case::Lower.apply(name), "arrow lefticon-24");
+        assert_eq!(Case::Upper.apply(name), "ARROW LEFTICON-24");
+        assert_eq!(Case::Snake.apply(name), "arrow_left_icon_24");
+        assert_eq!(Case::Kebab.apply(name), "arrow-left-icon-24");
+        assert_eq!(Case::Camel.apply(name), "arrowLeftIcon24");
+        assert_eq!(Case::Pascal.apply(name), "ArrowLeftIcon24");
+        assert!("title".parse::<Case>().is_err());
+    }
+
+    #[test]
+    fn naming_policy_should_normalize_every_segment() {
+        let policy = NamingPolicy {
+            case: Some(Case::Snake),
+            transliterate: true,
+            replacement: "_".to_string(),
+        };
+
+        assert_eq!(
+            policy.apply("Íconos/Flecha Izquierda 🦀"),
+            "iconos/flecha_izquierda"
+        );
+        assert_eq!(policy.apply("🦀"), "🦀");
+        assert_eq!(
+            policy.apply_to_file_name("Íconos/Niño Feliz.png"),
+            "iconos/nino_feliz.png"
+        );
+    }
+
+    #[test]
+    fn cases_should_keep_non_ascii_letters() {
+        let policy = NamingPolicy {
+            case: Some(Case::Snake),
+            ..NamingPolicy::default()
+        };
+
+        assert_eq!(policy.apply("Café"), "café");
+        assert_eq!(policy.apply("Niño Feliz"), "niño_feliz");
+        assert_ne!(policy.apply("Niño"), policy.apply("Ni o"));
+        assert_eq!(Case::Camel.apply("élan vital"), "élanVital");
+        assert_eq!(Case::Pascal.apply("ÉcoleÉté"), "ÉcoleÉté");
+    }
+
+    #[test]
+    fn naming_policy_should_replace_forbidden_characters() {
+        let policy = NamingPolicy {
+            replacement: "-".to_string(),
+            ..NamingPolicy::default()
+        };
+
+        assert_eq!(policy.apply("Yes? No: Maybe"), "Yes- No- Maybe");
+        assert_eq!(policy.apply("Café"), "Café");
+        assert_eq!(NamingPolicy::default().apply("a|b"), "a_b");
+    }
+
+    #[test]
+    fn segments_should_be_sanitized() {
+        assert_eq!(
+            sanitize_segment(" arrow-left "),
+            Some("arrow-left".to_string())
+        );
+        assert_eq!(sanitize_segment("a:b?c*"), Some("a_b_c_".to_string()));
+        assert_eq!(sanitize_segment("icon."), Some("icon".to_string()));
+        assert_eq!(sanitize_segment("CON"), Some("_CON".to_string()));
+        assert_eq!(sanitize_segment("nul.png"), Some("_nul.png".to_string()));
+        assert_eq!(sanitize_segment(".."), None);
+        assert_eq!(sanitize_segment("."), None);
+        assert_eq!(sanitize_segment("   "), None);
+    }
+}
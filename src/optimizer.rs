@@ -0,0 +1,501 @@
+use crate::{
+    emojis::{ERROR, FRAME},
+    jpg_optimizer::{self, JpgOptions},
+    png_quantizer::{self, QuantizationConfig},
+    progress::Stage,
+    svg_optimizer::{self, SvgOptions},
+};
+use console::style;
+use futures::FutureExt;
+use log::{error, info};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+/// oxipng level used for the quantized images when `--opt-png-level` is not set,
+/// as the palette only makes them smaller once oxipng stores them as indexed pngs
+const DEFAULT_QUANTIZED_PNG_LEVEL: u8 = 2;
+
+/// Levels used to optimize each one of the supported formats
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OptimizationOptions {
+    pub png_level: Option<u8>,
+    pub png_quantization: QuantizationConfig,
+    pub jpg: Option<JpgOptions>,
+    pub svg: Option<SvgOptions>,
+}
+
+/// Sizes and duration of the optimization of a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizationStats {
+    pub original_size: u64,
+    /// Size of the file once optimized, same as the original if the optimization was skipped
+    pub optimized_size: u64,
+    /// True when the optimized output was larger than the original, which is kept
+    pub skipped: bool,
+    pub duration: Duration,
+}
+
+impl OptimizationStats {
+    pub fn saved_bytes(&self) -> u64 {
+        self.original_size.saturating_sub(self.optimized_size)
+    }
+
+    /// Optimized size divided by the original size, lower is better
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            1.0
+        } else {
+            self.optimized_size as f64 / self.original_size as f64
+        }
+    }
+}
+
+/// Outcome of the optimization of a single file
+#[derive(Debug)]
+pub struct OptimizationResult {
+    pub path: PathBuf,
+    /// None when there's no optimization level set for the format or the optimization failed
+    pub stats: Option<OptimizationStats>,
+    pub error: Option<anyhow::Error>,
+}
+
+/// Runs the optimizations in tokio's blocking pool, limiting how many of them run at the same time
+#[derive(Clone)]
+pub struct Optimizer {
+    options: Arc<OptimizationOptions>,
+    permits: Arc<Semaphore>,
+}
+
+impl Optimizer {
+    pub fn new(options: OptimizationOptions, threads: Option<usize>) -> Self {
+        let threads = threads
+            .filter(|t| *t > 0)
+            .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+            .unwrap_or(1);
+        Self {
+            options: Arc::new(options),
+            permits: Arc::new(Semaphore::new(threads)),
+        }
+    }
+
+    /// Runs CPU heavy work once there's a free worker, so resizing or encoding images
+    /// shares the limit of the optimizations and doesn't block the runtime
+    pub async fn run_blocking<T, F>(&self, work: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    {
+        // the semaphore is never closed so acquiring a permit can't fail
+        let _permit = self.permits.acquire().await.ok();
+        tokio::task::spawn_blocking(work)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r)
+    }
+
+    /// Optimizes the image once there's a free worker
+    pub async fn optimize(&self, path: PathBuf, extension: String) -> OptimizationResult {
+        let options = Arc::clone(&self.options);
+        let task_path = path.clone();
+        let result = self
+            .run_blocking(move || optimize_file(&task_path, &extension, &options))
+            .await;
+
+        let (stats, error) = match result {
+            Err(e) => (None, Some(e)),
+            // validate that it exists
+            Ok(_) if !Path::exists(&path) => (None, Some(anyhow::anyhow!("FILE DELETED"))),
+            Ok(stats) => (stats, None),
+        };
+        if let Some(e) = &error {
+            error!("{} Error optimizing image {:?} => {:?}", ERROR, &path, e);
+        } else if let Some(stats) = &stats {
+            print_optimized_image(&path, stats);
+        }
+        OptimizationResult { path, stats, error }
+    }
+
+    /// Optimizes all the images concurrently
+    pub async fn optimize_all(
+        &self,
+        paths: Vec<PathBuf>,
+        stage: &Stage,
+    ) -> Vec<OptimizationResult> {
+        let futures = paths.into_iter().map(|path| {
+            let extension = path
+                .extension()
+                .and_then(std::ffi::OsStr::to_str)
+                .unwrap_or("png")
+                .to_string();
+            self.optimize(path, extension).inspect(|result| {
+                if result.error.is_none() {
+                    stage.inc_optimized();
+                }
+                stage.inc();
+            })
+        });
+        futures::future::join_all(futures).await
+    }
+}
+
+/// Optimizes the file in place, unless the optimized output is larger than the original
+fn optimize_file(
+    path: &Path,
+    extension: &str,
+    options: &OptimizationOptions,
+) -> anyhow::Result<Option<OptimizationStats>> {
+    let start = Instant::now();
+    let original = std::fs::read(path)?;
+    let optimized = match optimize_image(path, &original, extension, options)? {
+        Some(optimized) => optimized,
+        None => return Ok(None),
+    };
+
+    let skipped = optimized.len() >= original.len();
+    if !skipped {
+        std::fs::write(path, &optimized)?;
+    }
+    let original_size = original.len() as u64;
+    Ok(Some(OptimizationStats {
+        original_size,
+        optimized_size: if skipped {
+            original_size
+        } else {
+            optimized.len() as u64
+        },
+        skipped,
+        duration: start.elapsed(),
+    }))
+}
+
+/// Returns the optimized image or None if there's no optimization level set for the format
+fn optimize_image(
+    path: &Path,
+    original: &[u8],
+    extension: &str,
+    options: &OptimizationOptions,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match extension {
+        "jpg" => {
+            if let Some(opts) = options.jpg {
+                print_optimizing_image(path);
+                return Ok(Some(jpg_optimizer::optimize(original, opts)?));
+            } else {
+                info!("Are you trying to optimize this image {:?}? Don't forget to set the --opt-jpg-level value", path);
+            }
+        }
+        "png" => {
+            let quantization = options.png_quantization.options_for(path);
+            let level = options
+                .png_level
+                .or_else(|| quantization.map(|_| DEFAULT_QUANTIZED_PNG_LEVEL));
+            if let Some(lvl) = level {
+                print_optimizing_image(path);
+                let opts = oxipng::Options::from_preset(lvl);
+                let quantized = match quantization {
+                    Some(quantization) => png_quantizer::quantize_png(original, quantization)?,
+                    None => None,
+                };
+                let png = quantized.as_deref().unwrap_or(original);
+                return Ok(Some(oxipng::optimize_from_memory(png, &opts)?));
+            } else {
+                info!("Are you trying to optimize this image {:?}? Don't forget to set the --opt-png-level value", path);
+            }
+        }
+        "svg" => {
+            if let Some(opts) = options.svg {
+                print_optimizing_image(path);
+                let svg = std::str::from_utf8(original)?;
+                return Ok(Some(svg_optimizer::optimize(svg, opts)?.into_bytes()));
+            } else {
+                info!("Are you trying to optimize this image {:?}? Don't forget to set the --opt-svg-level value", path);
+            }
+        }
+        _ => (),
+    }
+    Ok(None)
+}
+
+fn print_optimizing_image(path: &Path) {
+    info!(
+        "{} {} {:?} ",
+        FRAME,
+        style("Optimizing image").yellow().bold(),
+        &path
+    );
+}
+
+fn print_optimized_image(path: &Path, stats: &OptimizationStats) {
+    let outcome = if stats.skipped {
+        style("Optimization Skipped").yellow().bold()
+    } else {
+        style("Image Optimized").green().bold()
+    };
+    info!(
+        "{} {} {:?} {} -> {} ({:+.1}%) in {}ms",
+        FRAME,
+        outcome,
+        path,
+        format_bytes(stats.original_size),
+        format_bytes(stats.optimized_size),
+        (stats.ratio() - 1.0) * 100.0,
+        stats.duration.as_millis(),
+    );
+}
+
+/// Optimization result of a single file, ready to be serialized
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSummary {
+    pub path: String,
+    pub original_size: u64,
+    pub optimized_size: u64,
+    pub saved_bytes: u64,
+    pub ratio: f64,
+    pub skipped: bool,
+    pub duration_ms: u128,
+}
+
+/// Aggregated results of the optimization of all the files
+#[derive(Debug, Default, Serialize)]
+pub struct OptimizationSummary {
+    pub optimized: usize,
+    pub skipped: usize,
+    pub failed: Vec<String>,
+    pub original_size: u64,
+    pub optimized_size: u64,
+    pub saved_bytes: u64,
+    pub best: Option<FileSummary>,
+    pub worst: Option<FileSummary>,
+    pub files: Vec<FileSummary>,
+}
+
+impl OptimizationSummary {
+    pub fn from_results(results: &[OptimizationResult]) -> Self {
+        let mut summary = Self::default();
+        for result in results {
+            let path = result.path.display().to_string();
+            if result.error.is_some() {
+                summary.failed.push(path);
+                continue;
+            }
+            let stats = match &result.stats {
+                Some(stats) => stats,
+                None => continue,
+            };
+            if stats.skipped {
+                summary.skipped += 1;
+            } else {
+                summary.optimized += 1;
+            }
+            summary.original_size += stats.original_size;
+            summary.optimized_size += stats.optimized_size;
+            summary.files.push(FileSummary {
+                path,
+                original_size: stats.original_size,
+                optimized_size: stats.optimized_size,
+                saved_bytes: stats.saved_bytes(),
+                ratio: stats.ratio(),
+                skipped: stats.skipped,
+                duration_ms: stats.duration.as_millis(),
+            });
+        }
+        summary.saved_bytes = summary.original_size - summary.optimized_size;
+        summary.files.sort_by(|a, b| a.path.cmp(&b.path));
+        summary.best = summary
+            .files
+            .iter()
+            .min_by(|a, b| a.ratio.total_cmp(&b.ratio))
+            .cloned();
+        summary.worst = summary
+            .files
+            .iter()
+            .max_by(|a, b| a.ratio.total_cmp(&b.ratio))
+            .cloned();
+        summary
+    }
+
+    /// Prints the totals and the best and worst ratios
+    #[allow(clippy::cast_precision_loss)]
+    pub fn print(&self) {
+        let saved_percentage = if self.original_size == 0 {
+            0.0
+        } else {
+            self.saved_bytes as f64 * 100.0 / self.original_size as f64
+        };
+        info!(
+            "{}  {}",
+            FRAME,
+            style(format!(
+                "{} images optimized, {} skipped, {} failed. Saved {} ({:.1}%)",
+                self.optimized,
+                self.skipped,
+                self.failed.len(),
+                format_bytes(self.saved_bytes),
+                saved_percentage,
+            ))
+            .bold()
+            .yellow()
+        );
+        if let (Some(best), Some(worst)) = (&self.best, &self.worst) {
+            info!("    Best:  {} ({:.1}%)", best.path, best.ratio * 100.0);
+            info!("    Worst: {} ({:.1}%)", worst.path, worst.ratio * 100.0);
+        }
+        for path in &self.failed {
+            info!("    {}", style(path).red());
+        }
+    }
+
+    /// Writes the summary as json
+    pub async fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn optimizer_should_report_one_result_per_file() -> anyhow::Result<()> {
+        let dir = crate::test_utils::temp_folder("fad_optimizer_test");
+        let svg_path = dir.join("a.svg");
+        tokio::fs::write(
+            &svg_path,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><!-- a --><g></g></svg>"#,
+        )
+        .await?;
+
+        let options = OptimizationOptions {
+            svg: Some(SvgOptions::new(1, 3)),
+            ..OptimizationOptions::default()
+        };
+        let optimizer = Optimizer::new(options, Some(2));
+        let results = optimizer
+            .optimize_all(
+                vec![svg_path.clone(), dir.join("unexisting.svg")],
+                &Stage::start(1, 1, "Optimizing", "files", 2),
+            )
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].error.is_none());
+        assert!(results[0].stats.as_ref().is_some_and(|s| !s.skipped));
+        assert!(results[1].error.is_some());
+        assert_eq!(
+            tokio::fs::read_to_string(&svg_path).await?,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"/>"#
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    fn result(path: &str, original_size: u64, optimized_size: u64) -> OptimizationResult {
+        OptimizationResult {
+            path: PathBuf::from(path),
+            stats: Some(OptimizationStats {
+                original_size,
+                optimized_size,
+                skipped: original_size == optimized_size,
+                duration: Duration::from_millis(1),
+            }),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn summary_should_aggregate_sizes_and_ratios() {
+        let results = vec![
+            result("a.png", 100, 50),
+            result("b.png", 100, 90),
+            result("c.png", 100, 100),
+            OptimizationResult {
+                path: PathBuf::from("d.png"),
+                stats: None,
+                error: Some(anyhow::anyhow!("boom")),
+            },
+        ];
+        let summary = OptimizationSummary::from_results(&results);
+
+        assert_eq!(summary.optimized, 2);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.failed, vec!["d.png".to_string()]);
+        assert_eq!(summary.saved_bytes, 60);
+        assert_eq!(summary.best.map(|f| f.path), Some("a.png".to_string()));
+        assert_eq!(summary.worst.map(|f| f.path), Some("c.png".to_string()));
+    }
+
+    #[test]
+    fn pngs_should_be_quantized_without_png_level() -> anyhow::Result<()> {
+        // 4096 colors
+        let img = image::RgbaImage::from_fn(64, 64, |x, y| {
+            image::Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        });
+        let mut png = vec![];
+        image::png::PngEncoder::new(&mut png).encode(
+            img.as_raw(),
+            64,
+            64,
+            image::ColorType::Rgba8,
+        )?;
+        let path = Path::new("illustration.png");
+
+        let lossless = OptimizationOptions::default();
+        assert!(optimize_image(path, &png, "png", &lossless)?.is_none());
+
+        let quantized = OptimizationOptions {
+            png_quantization: QuantizationConfig {
+                global: Some(png_quantizer::QuantizationOptions::default()),
+                ..QuantizationConfig::default()
+            },
+            ..OptimizationOptions::default()
+        };
+        let optimized =
+            optimize_image(path, &png, "png", &quantized)?.expect("png should be quantized");
+        let colors = image::load_from_memory(&optimized)?
+            .to_rgba8()
+            .pixels()
+            .map(|p| p.0)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(colors.len() <= 256);
+        Ok(())
+    }
+
+    #[test]
+    fn outputs_that_are_not_smaller_should_not_be_written() -> anyhow::Result<()> {
+        let dir = crate::test_utils::temp_folder("fad_optimizer_skip_test");
+        let path = dir.join("a.svg");
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"/>"#;
+        std::fs::write(&path, svg)?;
+
+        let options = OptimizationOptions {
+            svg: Some(SvgOptions::new(1, 3)),
+            ..OptimizationOptions::default()
+        };
+        let stats = optimize_file(&path, "svg", &options)?.expect("svg should be optimized");
+
+        assert!(stats.skipped);
+        assert_eq!(stats.optimized_size, stats.original_size);
+        assert_eq!(std::fs::read_to_string(&path)?, svg);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
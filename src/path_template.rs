@@ -0,0 +1,305 @@
+use crate::{
+    models::Image,
+    naming::{sanitize_segment, Case},
+};
+use std::{path::PathBuf, str::FromStr};
+
+/// Layout used when no template is set: `name.ext` for 1x images and `2.0x/name.ext` for the rest
+pub const DEFAULT_PATH_TEMPLATE: &str = "{scale_folder}/{name}.{ext}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Name,
+    Ext,
+    Scale,
+    ScaleFolder,
+    Suffix,
+    NodeId,
+    Page,
+}
+
+impl FromStr for Variable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "ext" | "format" => Ok(Self::Ext),
+            "scale" => Ok(Self::Scale),
+            "scale_folder" => Ok(Self::ScaleFolder),
+            "suffix" => Ok(Self::Suffix),
+            "node_id" => Ok(Self::NodeId),
+            "page" => Ok(Self::Page),
+            _ => Err(anyhow::anyhow!(
+                "Unknown path template variable {}. Use name, ext, format, scale, scale_folder, suffix, node_id or page",
+                s
+            )),
+        }
+    }
+}
+
+impl Variable {
+    fn value(self, image: &Image) -> String {
+        match self {
+            Self::Name => image.name.trim().to_string(),
+            Self::Ext => image.format.clone(),
+            Self::Scale => image.scale.to_string(),
+            Self::ScaleFolder => image.scale.folder().unwrap_or_default(),
+            Self::Suffix => image
+                .scale
+                .folder()
+                .map(|_| format!("@{}x", image.scale))
+                .unwrap_or_default(),
+            // colons are not allowed in windows paths
+            Self::NodeId => image.id.replace(':', "-"),
+            Self::Page => image.page.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Variable {
+        variable: Variable,
+        /// Slash separated segment of the value, negative indexes start from the end
+        segment: Option<isize>,
+        case: Option<Case>,
+    },
+}
+
+/// Template of the paths of the images relative to the download folder,
+/// like `{format}/{scale}x/{page}/{name|snake}{suffix}.{ext}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    parts: Vec<Part>,
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        DEFAULT_PATH_TEMPLATE
+            .parse()
+            .expect("The default path template is always valid")
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow::anyhow!("Unclosed variable in path template {}", s))?;
+            parts.push(parse_variable(&rest[start + 1..end])?);
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(anyhow::anyhow!("Unopened variable in path template {}", s));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        if !parts.iter().any(|p| {
+            matches!(
+                p,
+                Part::Variable {
+                    variable: Variable::Name | Variable::NodeId,
+                    ..
+                }
+            )
+        }) {
+            return Err(anyhow::anyhow!(
+                "Path template {} must contain {{name}} or {{node_id}} so every asset gets its own file",
+                s
+            ));
+        }
+        Ok(Self { parts })
+    }
+}
+
+/// Parses `variable`, `variable[index]` and `variable|case`
+fn parse_variable(expression: &str) -> anyhow::Result<Part> {
+    let (variable, case) = match expression.split_once('|') {
+        Some((variable, case)) => (variable, Some(case.trim().parse()?)),
+        None => (expression, None),
+    };
+    let (variable, segment) = match variable.trim().split_once('[') {
+        Some((variable, index)) => {
+            let index = index
+                .strip_suffix(']')
+                .and_then(|i| i.parse::<isize>().ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid segment index in {{{}}}", expression))?;
+            (variable, Some(index))
+        }
+        None => (variable.trim(), None),
+    };
+    Ok(Part::Variable {
+        variable: variable.parse()?,
+        segment,
+        case,
+    })
+}
+
+impl PathTemplate {
+    /// Path of the image relative to the download folder.
+    /// Slashes create folders, every folder is sanitized and empty folders and `..` are skipped.
+    pub fn render(&self, image: &Image) -> PathBuf {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Variable {
+                    variable,
+                    segment,
+                    case,
+                } => {
+                    let value = variable.value(image);
+                    let value = match segment {
+                        Some(index) => select_segment(&value, *index),
+                        None => value,
+                    };
+                    // the case is applied per folder to keep the separators
+                    let value = match case {
+                        Some(case) => value
+                            .split('/')
+                            .map(|v| case.apply(v))
+                            .collect::<Vec<_>>()
+                            .join("/"),
+                        None => value,
+                    };
+                    rendered.push_str(&value);
+                }
+            }
+        }
+        rendered.split('/').filter_map(sanitize_segment).collect()
+    }
+}
+
+fn select_segment(value: &str, index: isize) -> String {
+    let segments = value.split('/').collect::<Vec<_>>();
+    let position = if index < 0 {
+        segments.len().checked_sub(index.unsigned_abs())
+    } else {
+        Some(index.unsigned_abs())
+    };
+    position
+        .and_then(|p| segments.get(p))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Scale;
+    use std::path::Path;
+
+    fn image(name: &str, scale: f32) -> Image {
+        Image::new(
+            "12:34".to_string(),
+            name,
+            Scale::new(scale).unwrap(),
+            "png".to_string(),
+            "".to_string(),
+        )
+        .with_page(Some("Mobile Icons".to_string()))
+    }
+
+    #[test]
+    fn default_template_should_keep_the_scale_folders() {
+        let template = PathTemplate::default();
+
+        assert_eq!(template.render(&image("a", 1.0)), PathBuf::from("a.png"));
+        assert_eq!(
+            template.render(&image("a", 2.0)),
+            Path::new("2.0x").join("a.png")
+        );
+    }
+
+    #[test]
+    fn variables_should_be_replaced() {
+        let template: PathTemplate = "{format}/{scale}x/{page|kebab}/{name}{suffix}.{ext}"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            template.render(&image("arrow", 1.5)),
+            ["png", "1.5x", "mobile-icons", "arrow@1.5x.png"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        assert_eq!(
+            template.render(&image("arrow", 1.0)),
+            ["png", "1x", "mobile-icons", "arrow.png"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+    }
+
+    #[test]
+    fn segments_and_cases_should_be_applied() {
+        let template: PathTemplate = "{name[0]|upper}/{name[-1]|snake}_{node_id}.{ext}"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            template.render(&image("icons/Arrow Left", 1.0)),
+            Path::new("ICONS").join("arrow_left_12-34.png")
+        );
+        assert_eq!(
+            template.render(&image("single", 1.0)),
+            Path::new("SINGLE").join("single_12-34.png")
+        );
+    }
+
+    #[test]
+    fn slashes_should_create_sanitized_folders() {
+        let template = PathTemplate::default();
+
+        assert_eq!(
+            template.render(&image("icons/navigation/arrow-left", 2.0)),
+            ["2.0x", "icons", "navigation", "arrow-left.png"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        assert_eq!(
+            template.render(&image("../../etc/passwd", 1.0)),
+            ["etc", "passwd.png"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            template.render(&image("/icons/ what? /a:b", 1.0)),
+            ["icons", "what_", "a_b.png"].iter().collect::<PathBuf>()
+        );
+    }
+
+    #[test]
+    fn paths_should_stay_in_the_download_folder() {
+        let template = PathTemplate::default();
+
+        assert_eq!(
+            template.render(&image("../../x", 1.0)),
+            PathBuf::from("x.png")
+        );
+        assert_eq!(
+            template.render(&image("/icons/./arrow", 2.0)),
+            ["2.0x", "icons", "arrow.png"].iter().collect::<PathBuf>()
+        );
+    }
+
+    #[test]
+    fn invalid_templates_should_be_rejected() {
+        assert!("{name".parse::<PathTemplate>().is_err());
+        assert!("name}.png".parse::<PathTemplate>().is_err());
+        assert!("{unknown}/{name}".parse::<PathTemplate>().is_err());
+        assert!("{name|title}".parse::<PathTemplate>().is_err());
+        assert!("{name[x]}".parse::<PathTemplate>().is_err());
+        assert!("{scale}/icon.png".parse::<PathTemplate>().is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{emojis::*, pruner::to_slash_path};
+use console::style;
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// What a run would do with a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanStatus {
+    /// The file doesn't exist yet
+    New,
+    /// The file exists and would be written again. Its contents are not compared,
+    /// so the new bytes may be the same.
+    Overwrite,
+    /// The file exists and would be kept as it is
+    Kept,
+    /// The file can't be exported
+    Skipped,
+    /// The file would be pruned
+    Deleted,
+}
+
+impl PlanStatus {
+    /// Status of a file that would be written. `keep_existing` if existing files are not overwritten.
+    pub fn of(path: &Path, keep_existing: bool) -> Self {
+        match (path.exists(), keep_existing) {
+            (false, _) => Self::New,
+            (true, true) => Self::Kept,
+            (true, false) => Self::Overwrite,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Overwrite => "overwrite",
+            Self::Kept => "kept",
+            Self::Skipped => "skipped",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedFile {
+    /// Path relative to the download folder, using slashes
+    pub path: String,
+    pub status: PlanStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanSummary {
+    pub new: usize,
+    pub overwrite: usize,
+    pub kept: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+}
+
+/// Files a run would write, keep or prune, without touching the file system
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub download_path: PathBuf,
+    pub summary: PlanSummary,
+    pub files: Vec<PlannedFile>,
+}
+
+impl Plan {
+    pub fn new(download_path: &Path) -> Self {
+        Self {
+            download_path: download_path.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a file given its absolute path. A file is only planned once.
+    pub fn add(&mut self, path: &Path, status: PlanStatus, node_id: Option<&str>) {
+        self.add_with_reason(path, status, node_id, None);
+    }
+
+    pub fn skip(&mut self, path: &Path, node_id: Option<&str>, reason: &str) {
+        self.add_with_reason(path, PlanStatus::Skipped, node_id, Some(reason));
+    }
+
+    fn add_with_reason(
+        &mut self,
+        path: &Path,
+        status: PlanStatus,
+        node_id: Option<&str>,
+        reason: Option<&str>,
+    ) {
+        let relative = path.strip_prefix(&self.download_path).unwrap_or(path);
+        let path = match to_slash_path(relative) {
+            Some(path) => path,
+            None => relative.display().to_string(),
+        };
+        if self.files.iter().any(|f| f.path == path) {
+            return;
+        }
+        match status {
+            PlanStatus::New => self.summary.new += 1,
+            PlanStatus::Overwrite => self.summary.overwrite += 1,
+            PlanStatus::Kept => self.summary.kept += 1,
+            PlanStatus::Skipped => self.summary.skipped += 1,
+            PlanStatus::Deleted => self.summary.deleted += 1,
+        }
+        // sorted by status and path so the plan only changes when the files do
+        let position = self
+            .files
+            .partition_point(|f| (f.status, &f.path) < (status, &path));
+        self.files.insert(
+            position,
+            PlannedFile {
+                path,
+                status,
+                node_id: node_id.map(String::from),
+                reason: reason.map(String::from),
+            },
+        );
+    }
+
+    /// Prints every file along with the totals
+    pub fn print(&self) {
+        info!(
+            "{}  {} {:?}",
+            INFO,
+            style("Dry run, nothing will be written to").bold().blue(),
+            self.download_path
+        );
+        for file in &self.files {
+            let label = format!("{:>9}", file.status.label());
+            let label = match file.status {
+                PlanStatus::New => style(label).green(),
+                PlanStatus::Overwrite => style(label).yellow(),
+                PlanStatus::Kept => style(label).dim(),
+                PlanStatus::Skipped | PlanStatus::Deleted => style(label).red(),
+            };
+            match &file.reason {
+                Some(reason) => info!("    {} {} ({})", label, file.path, reason),
+                None => info!("    {} {}", label, file.path),
+            }
+        }
+        info!(
+            "{}  {}",
+            FRAME,
+            style(format!(
+                "{} new, {} overwrite, {} kept, {} skipped, {} deleted",
+                self.summary.new,
+                self.summary.overwrite,
+                self.summary.kept,
+                self.summary.skipped,
+                self.summary.deleted,
+            ))
+            .bold()
+            .yellow()
+        );
+    }
+
+    /// Writes the plan as json
+    pub async fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_should_depend_on_the_existing_files() {
+        let existing = std::env::temp_dir();
+        let missing = existing.join("fad_plan_missing_file.png");
+
+        assert_eq!(PlanStatus::of(&missing, false), PlanStatus::New);
+        assert_eq!(PlanStatus::of(&missing, true), PlanStatus::New);
+        assert_eq!(PlanStatus::of(&existing, false), PlanStatus::Overwrite);
+        assert_eq!(PlanStatus::of(&existing, true), PlanStatus::Kept);
+    }
+
+    #[test]
+    fn plan_should_count_every_file_once() {
+        let download_path = Path::new("/project/downloads");
+        let mut plan = Plan::new(download_path);
+        plan.add(
+            &download_path.join("b.png"),
+            PlanStatus::Overwrite,
+            Some("1:2"),
+        );
+        plan.add(
+            &download_path.join("2.0x/a.png"),
+            PlanStatus::New,
+            Some("1:1"),
+        );
+        plan.add(
+            &download_path.join("2.0x/a.png"),
+            PlanStatus::New,
+            Some("1:1"),
+        );
+        plan.skip(&download_path.join("c.png"), Some("1:3"), "not rendered");
+
+        assert_eq!(
+            plan.summary,
+            PlanSummary {
+                new: 1,
+                overwrite: 1,
+                skipped: 1,
+                ..PlanSummary::default()
+            }
+        );
+        let paths = plan
+            .files
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["2.0x/a.png", "b.png", "c.png"]);
+
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["files"][2]["reason"], "not rendered");
+        assert_eq!(json["files"][0]["status"], "new");
+    }
+}
@@ -0,0 +1,253 @@
+use crate::glob::glob_matches;
+use color_quant::NeuQuant;
+use serde::Deserialize;
+use std::path::Path;
+
+const DEFAULT_MAX_COLORS: u16 = 256;
+const DEFAULT_DITHERING: f32 = 1.0;
+const DEFAULT_MIN_QUALITY: u8 = 0;
+/// NeuQuant sampling factor, 1 is the slowest and best, 30 the fastest
+const SAMPLE_FACTOR: i32 = 10;
+
+/// Settings of the lossy palette quantization applied to png images before oxipng
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationOptions {
+    /// From 2 to 256
+    pub max_colors: u16,
+    /// From 0.0 (no dithering) to 1.0 (full Floyd-Steinberg dithering)
+    pub dithering: f32,
+    /// From 0 to 100. If the quantized image has a lower quality, the original one is kept.
+    pub min_quality: u8,
+}
+
+impl Default for QuantizationOptions {
+    fn default() -> Self {
+        Self {
+            max_colors: DEFAULT_MAX_COLORS,
+            dithering: DEFAULT_DITHERING,
+            min_quality: DEFAULT_MIN_QUALITY,
+        }
+    }
+}
+
+/// Quantization settings for the assets matching a pattern, only available in the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QuantizationRule {
+    /// Glob pattern (`*` and `?`) matched against the asset name or its path
+    pub pattern: String,
+    /// Set it to false to keep the matching assets lossless
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub max_colors: Option<u16>,
+    pub dithering: Option<f32>,
+    pub min_quality: Option<u8>,
+}
+
+const fn default_enabled() -> bool {
+    true
+}
+
+/// Global quantization settings along with the rules for specific assets
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QuantizationConfig {
+    /// Settings applied to the assets that don't match any rule, None if disabled
+    pub global: Option<QuantizationOptions>,
+    /// Base settings for the rules
+    pub defaults: QuantizationOptions,
+    pub rules: Vec<QuantizationRule>,
+}
+
+impl QuantizationConfig {
+    /// Settings for the file, the first matching rule wins
+    pub fn options_for(&self, path: &Path) -> Option<QuantizationOptions> {
+        let stem = path
+            .file_stem()
+            .and_then(std::ffi::OsStr::to_str)
+            .unwrap_or_default();
+        let full_path = path.to_string_lossy().replace('\\', "/");
+        let rule = self
+            .rules
+            .iter()
+            .find(|r| glob_matches(&r.pattern, stem) || glob_matches(&r.pattern, &full_path));
+
+        match rule {
+            Some(rule) if rule.enabled => Some(QuantizationOptions {
+                max_colors: rule.max_colors.unwrap_or(self.defaults.max_colors),
+                dithering: rule.dithering.unwrap_or(self.defaults.dithering),
+                min_quality: rule.min_quality.unwrap_or(self.defaults.min_quality),
+            }),
+            Some(_) => None,
+            None => self.global,
+        }
+    }
+}
+
+/// Quantizes a png image, returns None if the result doesn't reach the minimum quality
+pub fn quantize_png(png: &[u8], options: QuantizationOptions) -> anyhow::Result<Option<Vec<u8>>> {
+    let img = image::load_from_memory(png)?.to_rgba8();
+    let (width, height) = img.dimensions();
+    match quantize(img.as_raw(), width, options) {
+        Some(quantized) => {
+            let mut encoded = vec![];
+            // oxipng will take care of converting it to an indexed png
+            image::png::PngEncoder::new(&mut encoded).encode(
+                &quantized,
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?;
+            Ok(Some(encoded))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reduces the colors of an rgba image to a palette.
+/// Returns None if the result doesn't reach the minimum quality.
+pub fn quantize(rgba: &[u8], width: u32, options: QuantizationOptions) -> Option<Vec<u8>> {
+    let colors = usize::from(options.max_colors.clamp(2, 256));
+    let quantizer = NeuQuant::new(SAMPLE_FACTOR, colors, rgba);
+    let quantized = remap(rgba, width as usize, &quantizer, options.dithering);
+
+    if quality(rgba, &quantized) < f64::from(options.min_quality) {
+        None
+    } else {
+        Some(quantized)
+    }
+}
+
+/// Maps every pixel to the palette, diffusing the error to the neighbours
+fn remap(rgba: &[u8], width: usize, quantizer: &NeuQuant, dithering: f32) -> Vec<u8> {
+    let dithering = dithering.clamp(0.0, 1.0);
+    let mut result = rgba.to_vec();
+    let mut errors = vec![0.0_f32; rgba.len()];
+
+    for (pixel_index, pixel) in rgba.chunks_exact(4).enumerate() {
+        let offset = pixel_index * 4;
+        let mut wanted = [0_u8; 4];
+        for channel in 0..4 {
+            let value = f32::from(pixel[channel]) + errors[offset + channel];
+            // clamped to the u8 range
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let value = value.round().clamp(0.0, 255.0) as u8;
+            wanted[channel] = value;
+        }
+
+        let index = quantizer.index_of(&wanted);
+        let chosen = quantizer.lookup(index).unwrap_or(wanted);
+        result[offset..offset + 4].copy_from_slice(&chosen);
+
+        if dithering == 0.0 {
+            continue;
+        }
+        let x = pixel_index % width;
+        let neighbours = [
+            (x + 1 < width, offset + 4, 7.0 / 16.0),
+            (x > 0, (offset + width * 4).wrapping_sub(4), 3.0 / 16.0),
+            (true, offset + width * 4, 5.0 / 16.0),
+            (x + 1 < width, offset + width * 4 + 4, 1.0 / 16.0),
+        ];
+        for channel in 0..4 {
+            let error = (f32::from(wanted[channel]) - f32::from(chosen[channel])) * dithering;
+            for (exists, neighbour, weight) in neighbours {
+                if exists && neighbour + channel < errors.len() {
+                    errors[neighbour + channel] += error * weight;
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Quality from 0 to 100 based on the PSNR, 20dB or less is 0 and 50dB or more is 100
+#[allow(clippy::cast_precision_loss)]
+fn quality(original: &[u8], quantized: &[u8]) -> f64 {
+    let squared_error: f64 = original
+        .iter()
+        .zip(quantized)
+        .map(|(a, b)| (f64::from(*a) - f64::from(*b)).powi(2))
+        .sum();
+    if squared_error == 0.0 || original.is_empty() {
+        return 100.0;
+    }
+    let mse = squared_error / original.len() as f64;
+    let psnr = 10.0 * (255.0 * 255.0 / mse).log10();
+    ((psnr - 20.0) * 100.0 / 30.0).clamp(0.0, 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut rgba = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                // always lower than 256 for the sizes used in the tests
+                #[allow(clippy::cast_possible_truncation)]
+                rgba.extend_from_slice(&[(x * 8) as u8, (y * 8) as u8, 128, 255]);
+            }
+        }
+        rgba
+    }
+
+    fn count_colors(rgba: &[u8]) -> usize {
+        rgba.chunks_exact(4)
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+    }
+
+    #[test]
+    fn quantize_should_limit_the_number_of_colors() {
+        let rgba = gradient(32, 32);
+        assert!(count_colors(&rgba) > 16);
+
+        for dithering in [0.0, 1.0] {
+            let options = QuantizationOptions {
+                max_colors: 16,
+                dithering,
+                min_quality: 0,
+            };
+            let quantized = quantize(&rgba, 32, options).expect("quality floor is 0");
+
+            assert_eq!(quantized.len(), rgba.len());
+            assert!(count_colors(&quantized) <= 16);
+        }
+    }
+
+    #[test]
+    fn quantize_should_respect_the_quality_floor() {
+        let rgba = gradient(32, 32);
+        let options = QuantizationOptions {
+            max_colors: 2,
+            dithering: 0.0,
+            min_quality: 100,
+        };
+
+        assert!(quantize(&rgba, 32, options).is_none());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rule = |pattern: &str, enabled, max_colors| QuantizationRule {
+            pattern: pattern.to_string(),
+            enabled,
+            max_colors,
+            dithering: None,
+            min_quality: None,
+        };
+        let config = QuantizationConfig {
+            global: None,
+            defaults: QuantizationOptions::default(),
+            rules: vec![rule("icon*", false, None), rule("*", true, Some(64))],
+        };
+
+        assert_eq!(config.options_for(Path::new("downloads/icon_a.png")), None);
+        assert_eq!(
+            config
+                .options_for(Path::new("downloads/hero.png"))
+                .map(|o| o.max_colors),
+            Some(64)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{emojis::*, logger, optimizer::format_bytes};
+use console::{style, Term};
+use log::{info, LevelFilter};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+const BAR_WIDTH: u64 = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+/// Percentage between the plain lines printed when stderr is not a terminal
+const PLAIN_STEP: u64 = 25;
+
+/// Last line drawn by the stage in progress, so the messages can be printed above it
+static ACTIVE_BAR: Mutex<Option<String>> = Mutex::new(None);
+
+/// One stage of a run, like resolving the urls or downloading the images.
+/// It draws a progress bar on stderr or, if it's not a terminal, prints a line every 25%.
+pub struct Stage {
+    label: String,
+    unit: &'static str,
+    total: u64,
+    done: AtomicU64,
+    bytes: AtomicU64,
+    optimized: AtomicU64,
+    start: Instant,
+    interactive: bool,
+    last_draw: Mutex<Option<Instant>>,
+    last_step: AtomicU64,
+}
+
+impl Stage {
+    /// Starts the stage `step` of `steps`, made of `total` units of work
+    pub fn start(step: usize, steps: usize, name: &str, unit: &'static str, total: usize) -> Self {
+        let interactive =
+            Term::stderr().is_term() && log::max_level() >= LevelFilter::Info && !logger::is_json();
+        Self {
+            label: format!("[{}/{}] {}", step, steps, name),
+            unit,
+            total: total as u64,
+            done: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            optimized: AtomicU64::new(0),
+            start: Instant::now(),
+            interactive,
+            last_draw: Mutex::new(None),
+            last_step: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks one unit of work as done
+    pub fn inc(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.update();
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.update();
+    }
+
+    pub fn inc_optimized(&self) {
+        self.optimized.fetch_add(1, Ordering::Relaxed);
+        self.update();
+    }
+
+    /// Removes the bar and prints the totals of the stage
+    pub fn finish(&self) {
+        self.clear();
+        info!(
+            "{}  {} {}",
+            THUMB,
+            style(&self.label).bold().green(),
+            self.counters(self.done.load(Ordering::Relaxed))
+        );
+    }
+
+    fn clear(&self) {
+        if self.interactive {
+            let mut active = ACTIVE_BAR.lock().unwrap_or_else(PoisonError::into_inner);
+            if active.take().is_some() {
+                let _ = Term::stderr().clear_line();
+            }
+        }
+    }
+
+    fn update(&self) {
+        let done = self.done.load(Ordering::Relaxed);
+        if self.interactive {
+            let mut last_draw = self
+                .last_draw
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if last_draw.is_some_and(|d| d.elapsed() < REDRAW_INTERVAL) && done < self.total {
+                return;
+            }
+            *last_draw = Some(Instant::now());
+            drop(last_draw);
+
+            let line = self.render(done);
+            let mut active = ACTIVE_BAR.lock().unwrap_or_else(PoisonError::into_inner);
+            let term = Term::stderr();
+            let _ = term.clear_line();
+            let _ = term.write_str(&line);
+            *active = Some(line);
+        } else if let Some(percentage) = (done * 100).checked_div(self.total) {
+            // only the first update reaching a step prints it
+            let step = percentage / PLAIN_STEP;
+            if step > self.last_step.fetch_max(step, Ordering::Relaxed) {
+                info!("{} {}", DOWN, console::strip_ansi_codes(&self.render(done)));
+            }
+        }
+    }
+
+    fn render(&self, done: u64) -> String {
+        let filled = (done * BAR_WIDTH)
+            .checked_div(self.total)
+            .map_or(BAR_WIDTH, |f| f.min(BAR_WIDTH));
+        let bar = format!(
+            "{}{}",
+            "█".repeat(filled as usize),
+            "░".repeat((BAR_WIDTH - filled) as usize)
+        );
+        let mut line = format!(
+            "{} {} {}",
+            style(&self.label).bold().blue(),
+            style(bar).cyan(),
+            self.counters(done)
+        );
+        if let Some(eta) = eta(self.start.elapsed(), done, self.total) {
+            line.push_str(&format!(" ETA {}", format_duration(eta)));
+        }
+        line
+    }
+
+    fn counters(&self, done: u64) -> String {
+        let mut counters = format!("{}/{} {}", done, self.total, self.unit);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        if bytes > 0 {
+            counters.push_str(&format!(", {}", format_bytes(bytes)));
+        }
+        let optimized = self.optimized.load(Ordering::Relaxed);
+        if optimized > 0 {
+            counters.push_str(&format!(", {} optimized", optimized));
+        }
+        counters
+    }
+}
+
+impl Drop for Stage {
+    // the bar must not outlive a stage that ends with an error
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Prints above the bar of the stage in progress, if any
+pub fn print_above(print: impl FnOnce()) {
+    let active = ACTIVE_BAR.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*active {
+        Some(line) => {
+            let term = Term::stderr();
+            let _ = term.clear_line();
+            print();
+            let _ = term.write_str(line);
+        }
+        None => print(),
+    }
+}
+
+/// Remaining time, assuming every unit of work takes the same time
+fn eta(elapsed: Duration, done: u64, total: u64) -> Option<Duration> {
+    if done == 0 || done >= total {
+        return None;
+    }
+    let per_unit = elapsed.as_secs_f64() / done as f64;
+    Some(Duration::from_secs_f64(per_unit * (total - done) as f64))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_should_extrapolate_the_elapsed_time() {
+        assert_eq!(eta(Duration::from_secs(10), 0, 4), None);
+        assert_eq!(
+            eta(Duration::from_secs(10), 1, 4),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(eta(Duration::from_secs(10), 4, 4), None);
+        assert_eq!(format_duration(Duration::from_secs(80)), "1m 20s");
+    }
+
+    #[test]
+    fn bar_should_show_the_counters() {
+        let stage = Stage::start(3, 4, "Downloading", "files", 4);
+        stage.done.store(2, Ordering::Relaxed);
+        stage.add_bytes(2048);
+        stage.inc_optimized();
+        let line = console::strip_ansi_codes(&stage.render(2)).to_string();
+
+        assert!(line.starts_with("[3/4] Downloading "), "{}", line);
+        assert!(line.contains(&format!("{}{}", "█".repeat(15), "░".repeat(15))));
+        assert!(line.contains("2/4 files, 2.0 KB, 1 optimized"), "{}", line);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{models::Image, report::RunReport};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+/// Record of the files produced by fad, stored in the download folder
+pub const RECORD_FILE_NAME: &str = ".fad_files.json";
+pub const DEFAULT_TRASH_PATH: &str = ".fad_trash";
+
+/// What to do with the files that are no longer exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PruneMode {
+    #[default]
+    Delete,
+    /// Moves the files to the trash folder, keeping their paths
+    Trash,
+    /// Only lists the files
+    List,
+}
+
+impl FromStr for PruneMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "delete" => Ok(Self::Delete),
+            "trash" => Ok(Self::Trash),
+            "list" | "dry-run" => Ok(Self::List),
+            _ => Err(anyhow::anyhow!(
+                "Unknown prune mode {}. Use delete, trash or list",
+                s
+            )),
+        }
+    }
+}
+
+/// Files produced by fad, relative to the download folder and using slashes
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct OwnedFiles {
+    pub files: BTreeSet<String>,
+}
+
+impl OwnedFiles {
+    /// Builds the record from absolute paths. Paths outside the download folder are ignored.
+    pub fn from_paths<'a>(download_path: &Path, paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let files = paths
+            .into_iter()
+            .filter_map(|p| p.strip_prefix(download_path).ok())
+            .filter_map(to_slash_path)
+            .filter(|p| p != RECORD_FILE_NAME)
+            .collect();
+        Self { files }
+    }
+
+    /// Reads the record of the previous run, empty if there's none.
+    /// Entries that are not plain relative paths, like `../a.png`, are dropped so they are never pruned.
+    pub fn read(download_path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read(download_path.join(RECORD_FILE_NAME)) {
+            Ok(bytes) => {
+                let mut record: Self = serde_json::from_slice(&bytes)?;
+                record.files.retain(|f| is_plain_relative_path(f));
+                Ok(record)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, download_path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        std::fs::write(download_path.join(RECORD_FILE_NAME), json)?;
+        Ok(())
+    }
+
+    /// Files of this record that are not in the current one
+    pub fn stale_files(&self, current: &Self) -> Vec<String> {
+        self.files.difference(&current.files).cloned().collect()
+    }
+}
+
+/// Deletes, moves to the trash or lists the stale files that still exist in the download folder.
+/// Returns the files that have been pruned, or would be pruned when listing them.
+pub fn prune(
+    download_path: &Path,
+    stale_files: &[String],
+    mode: PruneMode,
+    trash_path: &Path,
+) -> Vec<(String, anyhow::Result<()>)> {
+    stale_files
+        .iter()
+        .filter(|f| {
+            let path = download_path.join(f);
+            is_plain_relative_path(f) && path.starts_with(download_path) && path.is_file()
+        })
+        .map(|file| {
+            let source = download_path.join(file);
+            let result = match mode {
+                PruneMode::List => Ok(()),
+                PruneMode::Delete => std::fs::remove_file(&source)
+                    .map_err(anyhow::Error::from)
+                    .map(|_| remove_empty_folders(download_path, &source)),
+                PruneMode::Trash => move_file(&source, &trash_path.join(file))
+                    .map(|_| remove_empty_folders(download_path, &source)),
+            };
+            (file.clone(), result)
+        })
+        .collect()
+}
+
+fn move_file(source: &Path, target: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // rename fails across devices
+    if std::fs::rename(source, target).is_err() {
+        std::fs::copy(source, target)?;
+        std::fs::remove_file(source)?;
+    }
+    Ok(())
+}
+
+/// Removes the folders left empty, without leaving the download folder
+fn remove_empty_folders(download_path: &Path, file: &Path) {
+    let mut folder = file.parent();
+    while let Some(current) = folder {
+        if current == download_path || !current.starts_with(download_path) {
+            break;
+        }
+        // fails if the folder is not empty
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        folder = current.parent();
+    }
+}
+
+/// Relative path using slashes, None if it isn't a plain relative path
+pub fn to_slash_path(path: &Path) -> Option<String> {
+    path.components()
+        .map(|c| match c {
+            Component::Normal(s) => s.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|segments| segments.join("/"))
+}
+
+/// Whether the path is relative, using slashes and without `.` or `..`, like the ones of the record
+fn is_plain_relative_path(path: &str) -> bool {
+    to_slash_path(Path::new(path)).as_deref() == Some(path)
+}
+
+/// Stale files are only known when the run got all its images. If a url batch or an app icon
+/// failed, its files would look stale while their frames are still in Figma.
+pub fn can_prune(images: &[Image], report: &RunReport) -> bool {
+    !images.is_empty() && report.errors.is_empty()
+}
+
+/// Absolute path of the trash folder
+pub fn trash_path(trash_path: Option<&str>) -> anyhow::Result<PathBuf> {
+    Ok(std::env::current_dir()?.join(trash_path.unwrap_or(DEFAULT_TRASH_PATH)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = crate::test_utils::temp_folder(name);
+        std::fs::create_dir_all(folder.join("icons")).unwrap();
+        folder
+    }
+
+    #[test]
+    fn runs_with_failed_batches_should_not_prune() {
+        let images = vec![Image::new(
+            "1:1".to_string(),
+            "a",
+            crate::models::Scale::ONE,
+            "png".to_string(),
+            "url".to_string(),
+        )];
+        let mut report = RunReport::default();
+
+        assert!(!can_prune(&[], &report));
+        assert!(can_prune(&images, &report));
+        report.add_error("Error getting images url from Figma API: timeout");
+        assert!(!can_prune(&images, &report));
+    }
+
+    #[test]
+    fn record_should_only_contain_files_in_the_download_folder() {
+        let download_path = Path::new("/project/downloads");
+        let paths = [
+            download_path.join("icons").join("a.png"),
+            download_path.join(RECORD_FILE_NAME),
+            PathBuf::from("/project/assets.ts"),
+        ];
+        let record = OwnedFiles::from_paths(download_path, paths.iter().map(PathBuf::as_path));
+
+        assert_eq!(
+            record.files.into_iter().collect::<Vec<_>>(),
+            vec!["icons/a.png".to_string()]
+        );
+    }
+
+    #[test]
+    fn stale_files_should_be_deleted_and_hand_added_files_kept() -> anyhow::Result<()> {
+        let folder = temp_folder("fad_prune_delete_test");
+        for file in &["icons/old.png", "new.png", "hand_added.png"] {
+            std::fs::write(folder.join(file), b"png")?;
+        }
+        let previous = OwnedFiles {
+            files: ["icons/old.png", "new.png"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        };
+        let current = OwnedFiles {
+            files: ["new.png"].iter().map(|s| s.to_string()).collect(),
+        };
+        let stale = previous.stale_files(&current);
+
+        let listed = prune(&folder, &stale, PruneMode::List, &folder.join("trash"));
+        assert_eq!(listed.len(), 1);
+        assert!(folder.join("icons/old.png").exists());
+
+        let pruned = prune(&folder, &stale, PruneMode::Delete, &folder.join("trash"));
+        assert_eq!(pruned[0].0, "icons/old.png");
+        assert!(pruned[0].1.is_ok());
+        assert!(!folder.join("icons").exists());
+        assert!(folder.join("new.png").exists());
+        assert!(folder.join("hand_added.png").exists());
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+
+    #[test]
+    fn stale_files_should_be_moved_to_the_trash() -> anyhow::Result<()> {
+        let folder = temp_folder("fad_prune_trash_test");
+        let downloads = folder.join("icons");
+        std::fs::write(downloads.join("old.png"), b"png")?;
+        let trash = folder.join("trash");
+
+        let pruned = prune(
+            &downloads,
+            &["old.png".to_string()],
+            PruneMode::Trash,
+            &trash,
+        );
+        assert!(pruned[0].1.is_ok());
+        assert!(!downloads.join("old.png").exists());
+        assert!(trash.join("old.png").exists());
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+
+    #[test]
+    fn record_should_be_read_and_written() -> anyhow::Result<()> {
+        let folder = temp_folder("fad_prune_record_test");
+        assert_eq!(OwnedFiles::read(&folder)?, OwnedFiles::default());
+
+        let record = OwnedFiles {
+            files: vec!["a.png".to_string()].into_iter().collect(),
+        };
+        record.write(&folder)?;
+        assert_eq!(OwnedFiles::read(&folder)?, record);
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+
+    #[test]
+    fn files_outside_the_download_folder_should_never_be_pruned() -> anyhow::Result<()> {
+        let folder = temp_folder("fad_prune_traversal_test");
+        let downloads = folder.join("icons");
+        std::fs::write(folder.join("main.rs"), b"fn main() {}")?;
+        std::fs::write(
+            downloads.join(RECORD_FILE_NAME),
+            r#"{"files": ["../main.rs", "/etc/hosts", "a/../../main.rs", "./a.png", "a.png"]}"#,
+        )?;
+
+        let record = OwnedFiles::read(&downloads)?;
+        assert_eq!(record.files.iter().collect::<Vec<_>>(), vec!["a.png"]);
+
+        let pruned = prune(
+            &downloads,
+            &["../main.rs".to_string(), "/etc/hosts".to_string()],
+            PruneMode::Delete,
+            &folder.join("trash"),
+        );
+        assert!(pruned.is_empty());
+        assert!(folder.join("main.rs").exists());
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{optimizer::OptimizationSummary, pruner::to_slash_path};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
+
+/// Format of the output of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The emoji messages
+    #[default]
+    Human,
+    /// The json report, printed to stdout once the run is done. Messages are written to stderr.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" | "text" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow::anyhow!(
+                "Unknown output format {}. Use human or json",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Downloaded,
+    Converted,
+    Resized,
+    AppIcon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Written,
+    /// Already in the download folder and not downloaded again
+    Kept,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReport {
+    /// Path relative to the download folder, using slashes
+    pub path: String,
+    pub kind: FileKind,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Everything a run did, meant for CI and bots
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    /// True if there are no errors and no file failed
+    pub success: bool,
+    pub file_id: Option<String>,
+    /// Version of the file set by `--file-version`, the latest one if missing
+    pub file_version: Option<String>,
+    pub document_ids: Vec<String>,
+    pub download_path: Option<PathBuf>,
+    /// Frames found in the documents
+    pub frames: usize,
+    /// Images requested to Figma, one per frame, scale and format
+    pub images_requested: usize,
+    /// Images rendered by Figma
+    pub images_resolved: usize,
+    pub files: Vec<FileReport>,
+    /// Files removed or moved to the trash by `--prune`
+    pub pruned: Vec<String>,
+    pub optimization: Option<OptimizationSummary>,
+    pub errors: Vec<String>,
+    /// Duration of every stage of the run
+    pub stages_ms: BTreeMap<String, u128>,
+    pub duration_ms: u128,
+}
+
+impl RunReport {
+    /// Adds a written file, or a failed one if there's an error
+    pub fn add_file(
+        &mut self,
+        path: &Path,
+        kind: FileKind,
+        node_id: Option<&str>,
+        error: Option<String>,
+    ) {
+        let status = if error.is_some() {
+            FileStatus::Failed
+        } else {
+            FileStatus::Written
+        };
+        self.push_file(path, kind, status, node_id, error);
+    }
+
+    /// Adds a file that is already in the download folder
+    pub fn add_kept_file(&mut self, path: &Path, kind: FileKind, node_id: Option<&str>) {
+        self.push_file(path, kind, FileStatus::Kept, node_id, None);
+    }
+
+    fn push_file(
+        &mut self,
+        path: &Path,
+        kind: FileKind,
+        status: FileStatus,
+        node_id: Option<&str>,
+        error: Option<String>,
+    ) {
+        let path = self.relative_path(path);
+        self.files.push(FileReport {
+            path,
+            kind,
+            status,
+            node_id: node_id.map(String::from),
+            bytes: None,
+            error,
+        });
+    }
+
+    pub fn relative_path(&self, path: &Path) -> String {
+        let relative = self
+            .download_path
+            .as_deref()
+            .and_then(|d| path.strip_prefix(d).ok())
+            .and_then(to_slash_path);
+        relative.unwrap_or_else(|| path.display().to_string())
+    }
+
+    pub fn add_error(&mut self, error: impl Into<String>) {
+        self.errors.push(error.into());
+    }
+
+    /// Records the duration of a stage since its start
+    pub fn add_stage(&mut self, name: &str, start: Instant) {
+        self.stages_ms
+            .insert(name.to_string(), start.elapsed().as_millis());
+    }
+
+    /// Sets the sizes of the files, the duration and the result of the run
+    pub fn finish(&mut self, start: Instant) {
+        for file in &mut self.files {
+            if file.status == FileStatus::Failed {
+                continue;
+            }
+            let path = match &self.download_path {
+                Some(download_path) => download_path.join(&file.path),
+                None => PathBuf::from(&file.path),
+            };
+            file.bytes = std::fs::metadata(path).ok().map(|m| m.len());
+        }
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        self.duration_ms = start.elapsed().as_millis();
+        self.success = self.errors.is_empty()
+            && self.files.iter().all(|f| f.status != FileStatus::Failed)
+            && self
+                .optimization
+                .as_ref()
+                .is_none_or(|o| o.failed.is_empty());
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Writes the report as json
+    pub async fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        tokio::fs::write(path, self.to_json()?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_format_should_be_parsed() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!(
+            "Human".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Human
+        );
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn report_should_contain_the_files_and_their_sizes() -> anyhow::Result<()> {
+        let folder = crate::test_utils::temp_folder("fad_report_test");
+        std::fs::create_dir_all(folder.join("2.0x"))?;
+        std::fs::write(folder.join("2.0x").join("a.png"), b"1234")?;
+        let mut report = RunReport {
+            download_path: Some(folder.clone()),
+            ..RunReport::default()
+        };
+
+        report.add_file(
+            &folder.join("2.0x").join("a.png"),
+            FileKind::Downloaded,
+            Some("1:1"),
+            None,
+        );
+        report.add_file(
+            &folder.join("a.webp"),
+            FileKind::Converted,
+            Some("1:1"),
+            Some("unsupported".to_string()),
+        );
+        report.finish(Instant::now());
+
+        assert!(!report.success);
+        assert_eq!(report.files[0].path, "2.0x/a.png");
+        assert_eq!(report.files[0].bytes, Some(4));
+        assert_eq!(report.files[1].status, FileStatus::Failed);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["files"][0]["kind"], "downloaded");
+        assert_eq!(json["files"][1]["error"], "unsupported");
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+}
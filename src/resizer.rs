@@ -0,0 +1,327 @@
+use crate::{
+    glob::glob_matches,
+    jpg_optimizer,
+    models::{Image, Scale},
+};
+use image::{imageops, imageops::FilterType, DynamicImage, GenericImageView, RgbaImage};
+use serde::Deserialize;
+use std::{convert::TryFrom, path::Path};
+
+const JPG_QUALITY: u8 = 90;
+
+/// How the image is placed in the target box when both dimensions are set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeMode {
+    /// The whole image is visible, the remaining space is transparent
+    #[default]
+    Fit,
+    /// The image covers the whole box, the overflow is cropped
+    Fill,
+}
+
+/// An extra output of exact pixel dimensions, only available in the config file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SizeOutput {
+    /// Glob pattern (`*` and `?`) matched against the asset name
+    pub pattern: String,
+    /// If only one dimension is set, the other one keeps the aspect ratio
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub mode: ResizeMode,
+    /// Removes the transparent borders before resizing
+    #[serde(default)]
+    pub trim: bool,
+    /// Transparent pixels around the image, included in the dimensions
+    #[serde(default)]
+    pub padding: u32,
+    /// Appended to the asset name, default: `_{width}x{height}`
+    pub suffix: Option<String>,
+}
+
+impl SizeOutput {
+    /// Square output fitting the whole image, used by the generated bundles
+    pub fn square(size: u32, padding: u32) -> Self {
+        Self {
+            pattern: String::new(),
+            width: Some(size),
+            height: Some(size),
+            mode: ResizeMode::Fit,
+            trim: false,
+            padding,
+            suffix: None,
+        }
+    }
+
+    pub fn suffix(&self) -> String {
+        match (&self.suffix, self.width, self.height) {
+            (Some(suffix), _, _) => suffix.clone(),
+            (None, Some(width), Some(height)) => format!("_{}x{}", width, height),
+            (None, Some(width), None) => format!("_w{}", width),
+            (None, None, Some(height)) => format!("_h{}", height),
+            (None, None, None) => String::new(),
+        }
+    }
+
+    /// Fails if the output has no dimensions to produce, or no room for its padding
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let too_small = |d: Option<u32>| d.is_some_and(|d| d <= self.padding.saturating_mul(2));
+        if self.padding.checked_mul(2).is_none() {
+            Err(anyhow::anyhow!(
+                "Size output {} has a padding too large",
+                self.pattern
+            ))
+        } else if self.width.is_none() && self.height.is_none() && !self.trim {
+            Err(anyhow::anyhow!(
+                "Size output {} needs a width, a height or trim = true",
+                self.pattern
+            ))
+        } else if too_small(self.width) || too_small(self.height) {
+            Err(anyhow::anyhow!(
+                "Size output {} has a padding larger than its dimensions",
+                self.pattern
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Pairs every size output with the highest scale image of the matching assets
+/// and the image it will produce, which is always at scale 1.
+pub fn plan<'a>(
+    images: &'a [Image],
+    outputs: &'a [SizeOutput],
+) -> Vec<(&'a SizeOutput, &'a Image, Image)> {
+    let mut plan = vec![];
+    for output in outputs {
+        let mut sources: Vec<&Image> = vec![];
+        for img in images
+            .iter()
+            .filter(|i| is_resizable(&i.format) && glob_matches(&output.pattern, &i.name))
+        {
+            match sources
+                .iter_mut()
+                .find(|s| s.name == img.name && s.format == img.format)
+            {
+                Some(source) if source.scale < img.scale => *source = img,
+                Some(_) => {}
+                None => sources.push(img),
+            }
+        }
+        for source in sources {
+            let mut target = source.clone();
+            target.name = format!("{}{}", source.name, output.suffix());
+            target.scale = Scale::ONE;
+            target.url = String::new();
+            plan.push((output, source, target));
+        }
+    }
+    plan
+}
+
+/// Only raster images can be resized
+pub fn is_resizable(extension: &str) -> bool {
+    matches!(extension, "png" | "jpg" | "jpeg")
+}
+
+/// Writes the resized version of the source image to the target path
+pub fn resize_file(source: &Path, target: &Path, output: &SizeOutput) -> anyhow::Result<()> {
+    let resized = resize(image::open(source)?, output);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match target.extension().and_then(std::ffi::OsStr::to_str) {
+        Some("jpg" | "jpeg") => {
+            let options = jpg_optimizer::JpgOptions::new(JPG_QUALITY);
+            let jpg = jpg_optimizer::encode(DynamicImage::ImageRgba8(resized), options)?;
+            std::fs::write(target, jpg)?;
+        }
+        _ => resized.save(target)?,
+    }
+    Ok(())
+}
+
+/// Trims, resizes and pads the image
+pub fn resize(img: DynamicImage, output: &SizeOutput) -> RgbaImage {
+    let img = if output.trim { trim(&img) } else { img };
+    let (width, height) = img.dimensions();
+    let padding = output.padding.saturating_mul(2);
+    let inner = |d: u32| d.saturating_sub(padding).max(1);
+
+    let (content, canvas_width, canvas_height) = match (output.width, output.height) {
+        (Some(w), Some(h)) => {
+            let content = match output.mode {
+                ResizeMode::Fit => img.resize(inner(w), inner(h), FilterType::Lanczos3),
+                ResizeMode::Fill => img.resize_to_fill(inner(w), inner(h), FilterType::Lanczos3),
+            };
+            (content, w, h)
+        }
+        (Some(w), None) => {
+            let h = scale_dimension(height, inner(w), width);
+            let content = img.resize_exact(inner(w), h, FilterType::Lanczos3);
+            (content, w, h.saturating_add(padding))
+        }
+        (None, Some(h)) => {
+            let w = scale_dimension(width, inner(h), height);
+            let content = img.resize_exact(w, inner(h), FilterType::Lanczos3);
+            (content, w.saturating_add(padding), h)
+        }
+        (None, None) => (
+            img,
+            width.saturating_add(padding),
+            height.saturating_add(padding),
+        ),
+    };
+
+    let mut canvas = RgbaImage::new(canvas_width, canvas_height);
+    let (content_width, content_height) = content.dimensions();
+    imageops::overlay(
+        &mut canvas,
+        &content.to_rgba8(),
+        (canvas_width - content_width) / 2,
+        (canvas_height - content_height) / 2,
+    );
+    canvas
+}
+
+/// Keeps the aspect ratio, `value * target / reference` rounded and never 0
+fn scale_dimension(value: u32, target: u32, reference: u32) -> u32 {
+    let scaled = (u64::from(value) * u64::from(target) + u64::from(reference) / 2)
+        / u64::from(reference.max(1));
+    u32::try_from(scaled).unwrap_or(u32::MAX).max(1)
+}
+
+/// Crops the fully transparent borders. Fully transparent images are left untouched.
+fn trim(img: &DynamicImage) -> DynamicImage {
+    let rgba = img.to_rgba8();
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        bounds = Some(match bounds {
+            Some((min_x, min_y, max_x, max_y)) => {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+    match bounds {
+        Some((min_x, min_y, max_x, max_y)) => {
+            img.crop_imm(min_x, min_y, max_x - min_x + 1, max_y - min_y + 1)
+        }
+        None => img.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(width: Option<u32>, height: Option<u32>) -> SizeOutput {
+        SizeOutput {
+            pattern: "*".to_string(),
+            width,
+            height,
+            mode: ResizeMode::Fit,
+            trim: false,
+            padding: 0,
+            suffix: None,
+        }
+    }
+
+    /// 8x4 transparent image with an opaque 4x2 red rectangle in the middle
+    fn bordered() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 4, |x, y| {
+            if (2..6).contains(&x) && (1..3).contains(&y) {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        }))
+    }
+
+    #[test]
+    fn fit_should_keep_the_whole_image_in_the_exact_dimensions() {
+        let opaque =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 4, image::Rgba([255, 0, 0, 255])));
+        let resized = resize(opaque, &output(Some(16), Some(16)));
+
+        assert_eq!(resized.dimensions(), (16, 16));
+        // 16x8 image centered vertically
+        assert_eq!(resized.get_pixel(8, 3)[3], 0);
+        assert_eq!(resized.get_pixel(8, 4)[3], 255);
+        assert_eq!(resized.get_pixel(8, 11)[3], 255);
+        assert_eq!(resized.get_pixel(8, 12)[3], 0);
+    }
+
+    #[test]
+    fn fill_should_cover_the_exact_dimensions() {
+        let mut fill = output(Some(4), Some(4));
+        fill.mode = ResizeMode::Fill;
+        fill.trim = true;
+        let resized = resize(bordered(), &fill);
+
+        assert_eq!(resized.dimensions(), (4, 4));
+        assert!(resized.pixels().all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn trim_and_padding_should_be_applied() {
+        let mut trimmed = output(None, None);
+        trimmed.trim = true;
+        trimmed.padding = 1;
+        let resized = resize(bordered(), &trimmed);
+
+        assert_eq!(resized.dimensions(), (6, 4));
+        assert_eq!(resized.get_pixel(0, 0)[3], 0);
+        assert_eq!(resized.get_pixel(1, 1)[3], 255);
+    }
+
+    #[test]
+    fn single_dimension_should_keep_the_aspect_ratio() {
+        assert_eq!(
+            resize(bordered(), &output(Some(4), None)).dimensions(),
+            (4, 2)
+        );
+        assert_eq!(
+            resize(bordered(), &output(None, Some(8))).dimensions(),
+            (16, 8)
+        );
+    }
+
+    #[test]
+    fn plan_should_use_the_highest_scale_image() {
+        let image = |scale| {
+            Image::new(
+                "1:1".to_string(),
+                "app_icon",
+                Scale::new(scale).unwrap(),
+                "png".to_string(),
+                "url".to_string(),
+            )
+        };
+        let images = vec![image(1.0), image(3.0), image(1.5)];
+        let outputs = vec![output(Some(32), Some(32))];
+        let plan = plan(&images, &outputs);
+
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].1.scale.value(), 3.0);
+        assert_eq!(plan[0].2.name, "app_icon_32x32");
+        assert_eq!(plan[0].2.scale, Scale::ONE);
+    }
+
+    #[test]
+    fn invalid_outputs_should_be_rejected() {
+        assert!(output(None, None).validate().is_err());
+        let mut padded = output(Some(4), None);
+        padded.padding = 2;
+        assert!(padded.validate().is_err());
+        let mut trimmed = output(None, None);
+        trimmed.trim = true;
+        trimmed.padding = u32::MAX;
+        assert!(trimmed.validate().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::models::Page;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+pub const DEFAULT_SNAPSHOT_PATH: &str = "fad_snapshot.json";
+
+/// Nodes of the documents of a Figma file, saved by the `snapshot` subcommand
+/// so the frames can be read later without network
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub file_id: String,
+    /// Version of the file when the snapshot was taken, so the images can be rendered from it
+    pub file_version: String,
+    /// Responses of the nodes endpoint by document id, as Figma returned them
+    pub nodes: BTreeMap<String, serde_json::Value>,
+}
+
+impl Snapshot {
+    pub fn new(file_id: impl Into<String>, file_version: impl Into<String>) -> Self {
+        Self {
+            file_id: file_id.into(),
+            file_version: file_version.into(),
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the response of the nodes endpoint for a document. Fails if it has no nodes, like an error response.
+    pub fn add(&mut self, document_id: &str, nodes: serde_json::Value) -> anyhow::Result<()> {
+        serde_json::from_value::<Page>(nodes.clone()).map_err(|e| {
+            anyhow::anyhow!("Figma didn't return the nodes of {}: {}", document_id, e)
+        })?;
+        self.nodes.insert(document_id.to_string(), nodes);
+        Ok(())
+    }
+
+    pub fn document_ids(&self) -> Vec<String> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    /// Nodes of the document, as the nodes endpoint returns them
+    pub fn nodes(&self, document_id: &str) -> anyhow::Result<serde_json::Value> {
+        self.nodes.get(document_id).cloned().ok_or_else(|| {
+            anyhow::anyhow!(
+                "The document {} is not in the snapshot. Take it again with this document id.",
+                document_id
+            )
+        })
+    }
+
+    /// Fails if the file or the version set by the user is not the one of the snapshot,
+    /// the frames would not match the rendered images
+    pub fn check_source(
+        &self,
+        file_id: Option<&str>,
+        file_version: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if let Some(file_id) = file_id.filter(|id| *id != self.file_id) {
+            return Err(anyhow::anyhow!(
+                "The snapshot was taken from the file {}, not {}",
+                self.file_id,
+                file_id
+            ));
+        }
+        if let Some(version) = file_version.filter(|v| *v != self.file_version) {
+            return Err(anyhow::anyhow!(
+                "The snapshot was taken from the version {}, not {}",
+                self.file_version,
+                version
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn read(path: &Path) -> anyhow::Result<Self> {
+        let json = tokio::fs::read(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Unable to read the snapshot {:?}: {}", path, e))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    pub async fn write(&self, path: &Path) -> anyhow::Result<()> {
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = include_str!("../fixtures/snapshot.json");
+
+    #[tokio::test]
+    async fn snapshots_should_be_read_back() -> anyhow::Result<()> {
+        let sample: Snapshot = serde_json::from_str(SAMPLE)?;
+        let mut snapshot = Snapshot::new("abc", "123");
+        snapshot.add("1:1", sample.nodes("1:1")?)?;
+        let path = crate::test_utils::temp_folder("fad_snapshot_test").join("snapshot.json");
+
+        snapshot.write(&path).await?;
+        let read = Snapshot::read(&path).await?;
+
+        assert_eq!(read, snapshot);
+        assert_eq!(read.document_ids(), vec!["1:1".to_string()]);
+        assert!(read.nodes("2:2").is_err());
+        std::fs::remove_dir_all(path.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn other_files_and_versions_should_be_rejected() {
+        let snapshot = Snapshot::new("abc", "123");
+
+        assert!(snapshot.check_source(None, None).is_ok());
+        assert!(snapshot.check_source(Some("abc"), Some("123")).is_ok());
+        assert!(snapshot.check_source(Some("def"), None).is_err());
+        assert!(snapshot.check_source(None, Some("456")).is_err());
+    }
+
+    #[test]
+    fn error_responses_should_not_be_added() {
+        let mut snapshot = Snapshot::new("abc", "123");
+        let error = serde_json::json!({"status": 403, "err": "Invalid token"});
+
+        assert!(snapshot.add("1:1", error).is_err());
+        assert!(snapshot.nodes.is_empty());
+    }
+
+    #[test]
+    fn frames_should_be_found_in_the_snapshot() -> anyhow::Result<()> {
+        let snapshot: Snapshot = serde_json::from_str(SAMPLE)?;
+        let page: Page = serde_json::from_value(snapshot.nodes("1:1")?)?;
+        let frames = page.into_frames("1:1");
+
+        assert_eq!(
+            frames.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+            vec!["arrow", "close.svg"]
+        );
+        assert_eq!(frames[0].page_name.as_deref(), Some("Icons"));
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::{collections::HashSet, fmt::Write};
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+/// Attributes whose numbers will be rounded to the configured precision
+const NUMERIC_ATTRIBUTES: &[&str] = &[
+    "d",
+    "points",
+    "transform",
+    "gradientTransform",
+    "patternTransform",
+    "viewBox",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "fx",
+    "fy",
+    "r",
+    "rx",
+    "ry",
+    "width",
+    "height",
+    "stroke-width",
+];
+/// Elements that can be safely removed when they have no children
+const CONTAINERS: &[&str] = &["g", "defs"];
+
+/// Settings of the svg optimization
+///
+/// - Level 1 removes comments, metadata, unused ids and empty groups and defs.
+/// - Level 2 also collapses useless groups and identity transforms.
+/// - Level 3 also rounds numbers to the configured precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgOptions {
+    pub level: u8,
+    pub precision: u8,
+}
+
+impl SvgOptions {
+    pub const fn new(level: u8, precision: u8) -> Self {
+        Self { level, precision }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SvgNode {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<SvgNode>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn remove_attribute(&mut self, name: &str) {
+        self.attributes.retain(|(n, _)| n != name);
+    }
+
+    fn set_attribute(&mut self, name: &str, value: String) {
+        if let Some(attr) = self.attributes.iter_mut().find(|(n, _)| n == name) {
+            attr.1 = value;
+        } else {
+            self.attributes.push((name.to_string(), value));
+        }
+    }
+
+    fn has_element_children(&self) -> bool {
+        self.children
+            .iter()
+            .any(|c| matches!(c, SvgNode::Element(_)))
+    }
+}
+
+/// Optimizes an svg document
+pub fn optimize(svg: &str, options: SvgOptions) -> anyhow::Result<String> {
+    let document = roxmltree::Document::parse(svg)?;
+    let mut root = to_element(document.root_element(), None);
+
+    remove_unused_ids(&mut root);
+    if options.level >= 2 {
+        collapse_groups(&mut root);
+    }
+    remove_empty_containers(&mut root);
+    if options.level >= 3 {
+        round_numbers(&mut root, options.precision);
+    }
+
+    let mut out = String::with_capacity(svg.len());
+    write_element(&mut out, &root);
+    Ok(out)
+}
+
+fn to_element(node: roxmltree::Node, parent: Option<roxmltree::Node>) -> Element {
+    let qualified_name = |namespace: Option<&str>, name: &str| match namespace {
+        Some(XML_NAMESPACE) => format!("xml:{}", name),
+        Some(ns) => match node.lookup_prefix(ns) {
+            Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name),
+            _ => name.to_string(),
+        },
+        None => name.to_string(),
+    };
+
+    let mut attributes = vec![];
+    let inherited = parent
+        .map(|p| {
+            p.namespaces()
+                .map(|ns| (ns.name(), ns.uri()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    for ns in node.namespaces() {
+        if ns.name() == Some("xml") || inherited.contains(&(ns.name(), ns.uri())) {
+            continue;
+        }
+        let name = ns
+            .name()
+            .map_or_else(|| "xmlns".to_string(), |n| format!("xmlns:{}", n));
+        attributes.push((name, ns.uri().to_string()));
+    }
+    for attr in node.attributes() {
+        attributes.push((
+            qualified_name(attr.namespace(), attr.name()),
+            attr.value().to_string(),
+        ));
+    }
+
+    let name = qualified_name(node.tag_name().namespace(), node.tag_name().name());
+    let keeps_whitespace = name == "text" || name == "tspan" || name == "style";
+    let children = node
+        .children()
+        .filter_map(|child| {
+            if child.is_element() {
+                if child.tag_name().name() == "metadata" {
+                    None
+                } else {
+                    Some(SvgNode::Element(to_element(child, Some(node))))
+                }
+            } else if child.is_text() {
+                child
+                    .text()
+                    .filter(|t| keeps_whitespace || !t.trim().is_empty())
+                    .map(|t| SvgNode::Text(t.to_string()))
+            } else {
+                // comments and processing instructions
+                None
+            }
+        })
+        .collect();
+
+    Element {
+        name,
+        attributes,
+        children,
+    }
+}
+
+fn remove_unused_ids(root: &mut Element) {
+    let mut references = HashSet::new();
+    let mut has_styles = false;
+    collect_references(root, &mut references, &mut has_styles);
+    // ids may be referenced by css selectors, we'd better leave them alone
+    if !has_styles {
+        remove_ids(root, &references);
+    }
+}
+
+fn collect_references(element: &Element, references: &mut HashSet<String>, has_styles: &mut bool) {
+    if element.name == "style" {
+        *has_styles = true;
+    }
+    for (name, value) in &element.attributes {
+        if name == "href" || name == "xlink:href" {
+            if let Some(id) = value.strip_prefix('#') {
+                references.insert(id.to_string());
+            }
+        }
+        let mut rest = value.as_str();
+        while let Some(start) = rest.find("url(") {
+            rest = &rest[start + 4..];
+            let end = rest.find(')').unwrap_or(rest.len());
+            let id = rest[..end].trim_matches(|c| c == '\'' || c == '"' || c == ' ');
+            if let Some(id) = id.strip_prefix('#') {
+                references.insert(id.to_string());
+            }
+            rest = &rest[end..];
+        }
+    }
+    for child in &element.children {
+        if let SvgNode::Element(e) = child {
+            collect_references(e, references, has_styles);
+        }
+    }
+}
+
+fn remove_ids(element: &mut Element, references: &HashSet<String>) {
+    if element
+        .attribute("id")
+        .is_some_and(|id| !references.contains(id))
+    {
+        element.remove_attribute("id");
+    }
+    for child in &mut element.children {
+        if let SvgNode::Element(e) = child {
+            remove_ids(e, references);
+        }
+    }
+}
+
+fn collapse_groups(element: &mut Element) {
+    let mut children = Vec::with_capacity(element.children.len());
+    for child in element.children.drain(..) {
+        match child {
+            SvgNode::Element(mut e) => {
+                collapse_groups(&mut e);
+                if let Some(transform) = e.attribute("transform") {
+                    if is_identity_transform(transform) {
+                        e.remove_attribute("transform");
+                    }
+                }
+                if e.name != "g" {
+                    children.push(SvgNode::Element(e));
+                } else if e.attributes.is_empty() {
+                    children.append(&mut e.children);
+                } else if let Some(inner) = single_child_with_transform(&mut e) {
+                    children.push(SvgNode::Element(inner));
+                } else {
+                    children.push(SvgNode::Element(e));
+                }
+            }
+            text => children.push(text),
+        }
+    }
+    element.children = children;
+}
+
+/// Moves the transform of a group with only a transform to its only child
+fn single_child_with_transform(group: &mut Element) -> Option<Element> {
+    if group.attributes.len() != 1 || group.children.len() != 1 {
+        return None;
+    }
+    let transform = group.attribute("transform")?.to_string();
+    match group.children.pop() {
+        Some(SvgNode::Element(mut child)) => {
+            let merged = match child.attribute("transform") {
+                Some(inner) => format!("{} {}", transform, inner),
+                None => transform,
+            };
+            child.set_attribute("transform", merged);
+            Some(child)
+        }
+        Some(other) => {
+            group.children.push(other);
+            None
+        }
+        None => None,
+    }
+}
+
+fn is_identity_transform(transform: &str) -> bool {
+    transform
+        .split(')')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .all(|f| {
+            let mut parts = f.splitn(2, '(');
+            let name = parts
+                .next()
+                .unwrap_or("")
+                .trim_matches(|c| c == ',' || c == ' ');
+            let args = parse_numbers(parts.next().unwrap_or(""));
+            match (name, args.as_slice()) {
+                ("translate", [x]) | ("translate", [x, 0.0]) => *x == 0.0,
+                ("scale", [x]) => *x == 1.0,
+                ("scale", [x, y]) => *x == 1.0 && *y == 1.0,
+                ("rotate", [a, ..]) | ("skewX", [a]) | ("skewY", [a]) => *a == 0.0,
+                ("matrix", [a, b, c, d, e, f]) => {
+                    [*a, *b, *c, *d, *e, *f] == [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+                }
+                _ => false,
+            }
+        })
+}
+
+fn parse_numbers(value: &str) -> Vec<f64> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f64>().unwrap_or(f64::NAN))
+        .collect()
+}
+
+fn remove_empty_containers(element: &mut Element) {
+    element.children.retain_mut(|child| match child {
+        SvgNode::Element(e) => {
+            remove_empty_containers(e);
+            !CONTAINERS.contains(&e.name.as_str()) || e.has_element_children()
+        }
+        SvgNode::Text(_) => true,
+    });
+}
+
+fn round_numbers(element: &mut Element, precision: u8) {
+    for (name, value) in &mut element.attributes {
+        if NUMERIC_ATTRIBUTES.contains(&name.as_str()) {
+            *value = round_numbers_in(value, precision);
+        }
+    }
+    for child in &mut element.children {
+        if let SvgNode::Element(e) = child {
+            round_numbers(e, precision);
+        }
+    }
+}
+
+/// Rounds all the decimal numbers found in a value, leaving the rest untouched
+fn round_numbers_in(value: &str, precision: u8) -> String {
+    let bytes = value.as_bytes();
+    let mut out = String::with_capacity(value.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let starts_number = bytes[i].is_ascii_digit()
+            || (bytes[i] == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+            || ((bytes[i] == b'-' || bytes[i] == b'+')
+                && bytes
+                    .get(i + 1)
+                    .is_some_and(|b| b.is_ascii_digit() || *b == b'.'));
+        if !starts_number {
+            out.push(value[i..].chars().next().unwrap_or_default());
+            i += value[i..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+
+        let start = i;
+        let mut is_decimal = false;
+        if bytes[i] == b'-' || bytes[i] == b'+' {
+            i += 1;
+        }
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i < bytes.len() && bytes[i] == b'.' {
+            is_decimal = true;
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+        if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+            let mut j = i + 1;
+            if j < bytes.len() && (bytes[j] == b'-' || bytes[j] == b'+') {
+                j += 1;
+            }
+            if j < bytes.len() && bytes[j].is_ascii_digit() {
+                is_decimal = true;
+                i = j;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+        }
+
+        let token = &value[start..i];
+        // integers are kept as they are, arc flags may be written without separators
+        match token.parse::<f64>() {
+            Ok(number) if is_decimal => out.push_str(&format_number(number, precision)),
+            _ => out.push_str(token),
+        }
+    }
+    out
+}
+
+fn format_number(number: f64, precision: u8) -> String {
+    let formatted = format!("{:.*}", usize::from(precision), number);
+    let trimmed = if formatted.contains('.') {
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        formatted.as_str()
+    };
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn write_element(out: &mut String, element: &Element) {
+    // writing to a String never fails
+    let _ = write!(out, "<{}", element.name);
+    for (name, value) in &element.attributes {
+        let _ = write!(out, " {}=\"{}\"", name, escape(value, true));
+    }
+    if element.children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for child in &element.children {
+        match child {
+            SvgNode::Element(e) => write_element(out, e),
+            SvgNode::Text(t) => out.push_str(&escape(t, false)),
+        }
+    }
+    let _ = write!(out, "</{}>", element.name);
+}
+
+fn escape(value: &str, is_attribute: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' if !is_attribute => escaped.push_str("&gt;"),
+            '"' if is_attribute => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIGMA_SVG: &str = r##"<svg width="24" height="24" viewBox="0 0 24 24" fill="none" xmlns="http://www.w3.org/2000/svg">
+<!-- exported from figma -->
+<g id="icon" clip-path="url(#clip0)">
+<g transform="translate(0 0)">
+<path id="Vector" d="M12.123456 4.5L7.0000001 10a1 1 0 011 1" fill="#1A1A1A"/>
+</g>
+<g></g>
+</g>
+<defs>
+<clipPath id="clip0">
+<rect width="24" height="24" fill="white"/>
+</clipPath>
+</defs>
+<defs/>
+</svg>
+"##;
+
+    #[test]
+    fn level_1_removes_comments_unused_ids_and_empty_containers() -> anyhow::Result<()> {
+        let result = optimize(FIGMA_SVG, SvgOptions::new(1, 3))?;
+
+        assert!(!result.contains("<!--"));
+        assert!(!result.contains("id=\"icon\""));
+        assert!(!result.contains("id=\"Vector\""));
+        assert!(result.contains("id=\"clip0\""));
+        assert!(!result.contains("<g></g>") && !result.contains("<g/>"));
+        assert!(!result.contains("<defs/>"));
+        assert!(result.contains("transform=\"translate(0 0)\""));
+        assert!(result.contains("M12.123456"));
+        Ok(())
+    }
+
+    #[test]
+    fn level_2_collapses_groups_and_identity_transforms() -> anyhow::Result<()> {
+        let result = optimize(FIGMA_SVG, SvgOptions::new(2, 3))?;
+
+        assert!(!result.contains("transform"));
+        assert!(result.contains("<g clip-path=\"url(#clip0)\"><path d="));
+        Ok(())
+    }
+
+    #[test]
+    fn level_3_rounds_numbers() -> anyhow::Result<()> {
+        let result = optimize(FIGMA_SVG, SvgOptions::new(3, 2))?;
+
+        assert!(result.contains("d=\"M12.12 4.5L7 10a1 1 0 011 1\""));
+        Ok(())
+    }
+
+    #[test]
+    fn group_transform_is_moved_to_its_only_child() -> anyhow::Result<()> {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><g transform="translate(1 2)"><rect transform="scale(2)" width="1" height="1"/></g></svg>"#;
+        let result = optimize(svg, SvgOptions::new(2, 3))?;
+
+        assert_eq!(
+            result,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><rect transform="translate(1 2) scale(2)" width="1" height="1"/></svg>"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn namespaced_references_are_kept() -> anyhow::Result<()> {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#image0"/><defs><image id="image0" width="1" height="1"/></defs></svg>"##;
+        let result = optimize(svg, SvgOptions::new(3, 3))?;
+
+        assert_eq!(result, svg);
+        Ok(())
+    }
+
+    #[test]
+    fn numbers_are_formatted_without_trailing_zeros() {
+        assert_eq!(format_number(1.5, 3), "1.5");
+        assert_eq!(format_number(-0.0001, 3), "0");
+        assert_eq!(
+            round_numbers_in("matrix(1.00001 0 0 1 -.123456 1e-7)", 3),
+            "matrix(1 0 0 1 -0.123 0)"
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates an empty folder in the system temp folder that no other test run or test uses.
+pub fn temp_folder(name: &str) -> PathBuf {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let folder = std::env::temp_dir().join(format!("{}_{}_{}", name, std::process::id(), id));
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(&folder).unwrap();
+    folder
+}
@@ -0,0 +1,216 @@
+use crate::{
+    emojis::*,
+    report::{FileStatus, RunReport},
+};
+use console::style;
+use log::{error, info};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_INTERVAL_SECS: u64 = 60;
+pub const DEFAULT_DEBOUNCE_SECS: u64 = 10;
+
+/// Decides when to sync, given the versions of the Figma file seen so far.
+/// A new version is only synced once it hasn't changed for the debounce time,
+/// so designers can keep editing without triggering a sync per change.
+#[derive(Debug, Default)]
+pub struct VersionTracker {
+    synced: Option<String>,
+    pending: Option<(String, Instant)>,
+}
+
+impl VersionTracker {
+    /// True if the file must be synced now. The first version is always synced.
+    pub fn observe(&mut self, version: &str, now: Instant, debounce: Duration) -> bool {
+        match &self.synced {
+            None => return true,
+            Some(synced) if synced == version => {
+                self.pending = None;
+                return false;
+            }
+            Some(_) => {}
+        }
+        match &self.pending {
+            Some((pending, since)) if pending == version => now.duration_since(*since) >= debounce,
+            _ => {
+                self.pending = Some((version.to_string(), now));
+                debounce.is_zero()
+            }
+        }
+    }
+
+    pub fn synced(&mut self, version: &str) {
+        self.synced = Some(version.to_string());
+        self.pending = None;
+    }
+
+    /// True while a new version is waiting for the debounce time
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+/// Hashes of the files, to find out which ones a sync changed
+#[derive(Debug, Default)]
+pub struct Fingerprints(BTreeMap<String, u64>);
+
+impl Fingerprints {
+    /// Hashes the files that exist. Paths are relative to the download folder.
+    pub fn read<'a>(download_path: &Path, files: impl IntoIterator<Item = &'a String>) -> Self {
+        Self(
+            files
+                .into_iter()
+                .filter_map(|f| fingerprint(&download_path.join(f)).map(|h| (f.clone(), h)))
+                .collect(),
+        )
+    }
+}
+
+fn fingerprint(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// What a sync changed in the download folder
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CycleSummary {
+    pub new: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: usize,
+    pub failed: Vec<String>,
+    pub pruned: Vec<String>,
+    pub errors: usize,
+}
+
+impl CycleSummary {
+    /// Compares the files written by the sync with the fingerprints taken before it
+    pub fn new(before: &Fingerprints, report: &RunReport) -> Self {
+        let download_path = report
+            .download_path
+            .as_deref()
+            .unwrap_or_else(|| Path::new(""));
+        let mut summary = Self {
+            pruned: report.pruned.clone(),
+            errors: report.errors.len(),
+            ..Self::default()
+        };
+        for file in &report.files {
+            match file.status {
+                FileStatus::Failed => summary.failed.push(file.path.clone()),
+                FileStatus::Kept => summary.unchanged += 1,
+                FileStatus::Written => match before.0.get(&file.path) {
+                    None => summary.new.push(file.path.clone()),
+                    Some(hash) if fingerprint(&download_path.join(&file.path)) == Some(*hash) => {
+                        summary.unchanged += 1
+                    }
+                    Some(_) => summary.updated.push(file.path.clone()),
+                },
+            }
+        }
+        summary
+    }
+
+    /// Prints the summary. The label tells what triggered the cycle, like the version of the file.
+    pub fn print(&self, cycle: usize, label: &str) {
+        info!(
+            "{}  {}",
+            CLOCK,
+            style(format!(
+                "Cycle {} ({}): {} new, {} updated, {} unchanged, {} pruned, {} failed",
+                cycle,
+                label,
+                self.new.len(),
+                self.updated.len(),
+                self.unchanged,
+                self.pruned.len(),
+                self.failed.len(),
+            ))
+            .bold()
+            .yellow()
+        );
+        for file in &self.new {
+            info!("    {} {}", style("new").green(), file);
+        }
+        for file in &self.updated {
+            info!("    {} {}", style("updated").yellow(), file);
+        }
+        for file in &self.pruned {
+            info!("    {} {}", style("pruned").red(), file);
+        }
+        for file in &self.failed {
+            error!("    {} {}", style("failed").red(), file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::FileKind;
+
+    #[test]
+    fn versions_should_be_synced_once_stable() {
+        let debounce = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut tracker = VersionTracker::default();
+
+        assert!(tracker.observe("1", start, debounce));
+        tracker.synced("1");
+        assert!(!tracker.observe("1", start, debounce));
+
+        // still being edited
+        assert!(!tracker.observe("2", start, debounce));
+        assert!(!tracker.observe("3", start + Duration::from_secs(5), debounce));
+        assert!(!tracker.observe("3", start + Duration::from_secs(10), debounce));
+        assert!(tracker.is_pending());
+        assert!(tracker.observe("3", start + Duration::from_secs(15), debounce));
+        tracker.synced("3");
+        assert!(!tracker.is_pending());
+
+        assert!(tracker.observe("4", start, Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn summary_should_tell_new_updated_and_unchanged_files() -> anyhow::Result<()> {
+        let folder = crate::test_utils::temp_folder("fad_watch_summary_test");
+        for file in &["same.png", "changed.png"] {
+            std::fs::write(folder.join(file), b"before")?;
+        }
+        let files = ["same.png", "changed.png"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        let before = Fingerprints::read(&folder, &files);
+
+        std::fs::write(folder.join("changed.png"), b"after")?;
+        std::fs::write(folder.join("new.png"), b"new")?;
+        let mut report = RunReport {
+            download_path: Some(folder.clone()),
+            ..RunReport::default()
+        };
+        for file in &["same.png", "changed.png", "new.png"] {
+            report.add_file(&folder.join(file), FileKind::Downloaded, None, None);
+        }
+        report.add_file(
+            &folder.join("broken.png"),
+            FileKind::Downloaded,
+            None,
+            Some("timeout".to_string()),
+        );
+        let summary = CycleSummary::new(&before, &report);
+
+        assert_eq!(summary.new, vec!["new.png".to_string()]);
+        assert_eq!(summary.updated, vec!["changed.png".to_string()]);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.failed, vec!["broken.png".to_string()]);
+
+        std::fs::remove_dir_all(&folder)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use crate::emojis::*;
+use hyper::{
+    body::HttpBody,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::{convert::Infallible, future::Future, net::SocketAddr, sync::Arc};
+use tokio::sync::Notify;
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 4000;
+/// Figma events are small, bigger bodies are rejected without reading them completely
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Event posted by a Figma webhook (v2).
+/// Only the fields needed to decide whether to sync are deserialized.
+#[derive(Debug, Deserialize)]
+pub struct WebhookEvent {
+    pub event_type: String,
+    pub passcode: String,
+    #[serde(default)]
+    pub file_key: Option<String>,
+}
+
+/// What the receiver does with a request
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The configured file changed and must be synced
+    Sync(String),
+    /// A valid event that doesn't need a sync, like a ping or an event of another file
+    Ignored(String),
+    /// The passcode doesn't match the one of the webhook
+    Unauthorized,
+    Invalid(String),
+}
+
+impl Outcome {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Sync(_) | Self::Ignored(_) => StatusCode::OK,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Self::Sync(file_key) => format!("syncing {}", file_key),
+            Self::Ignored(reason) => format!("ignored: {}", reason),
+            Self::Unauthorized => "invalid passcode".to_string(),
+            Self::Invalid(reason) => format!("invalid event: {}", reason),
+        }
+    }
+}
+
+/// Decides which events trigger a sync of the configured file
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    passcode: String,
+    file_id: String,
+}
+
+impl Receiver {
+    /// Fails if the passcode is empty, as any event with an empty passcode would be accepted
+    pub fn new(passcode: impl Into<String>, file_id: impl Into<String>) -> anyhow::Result<Self> {
+        let passcode = passcode.into();
+        if passcode.trim().is_empty() {
+            return Err(anyhow::anyhow!(
+                "The passcode can't be empty. Use the passcode of the Figma webhook."
+            ));
+        }
+        Ok(Self {
+            passcode,
+            file_id: file_id.into(),
+        })
+    }
+
+    pub fn handle(&self, body: &[u8]) -> Outcome {
+        let event: WebhookEvent = match serde_json::from_slice(body) {
+            Ok(event) => event,
+            Err(e) => return Outcome::Invalid(e.to_string()),
+        };
+        if !constant_time_eq(event.passcode.as_bytes(), self.passcode.as_bytes()) {
+            return Outcome::Unauthorized;
+        }
+        match event.event_type.as_str() {
+            "FILE_UPDATE" | "LIBRARY_PUBLISH" => match event.file_key {
+                Some(file_key) if file_key == self.file_id => Outcome::Sync(file_key),
+                Some(file_key) => {
+                    Outcome::Ignored(format!("the file {} is not configured", file_key))
+                }
+                None => Outcome::Invalid(format!("{} without file_key", event.event_type)),
+            },
+            "PING" => Outcome::Ignored("ping".to_string()),
+            other => Outcome::Ignored(format!("the event {} doesn't need a sync", other)),
+        }
+    }
+}
+
+/// Compares the passcodes without leaking how many bytes match
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Binds the webhook server to the address. Every event that needs a sync
+/// wakes `sync_requests` up, so events received during a sync lead to a single new one.
+/// Returns the bound address, useful with port 0, and the server to await.
+pub fn bind(
+    addr: SocketAddr,
+    receiver: Receiver,
+    sync_requests: Arc<Notify>,
+) -> anyhow::Result<(SocketAddr, impl Future<Output = hyper::Result<()>>)> {
+    let receiver = Arc::new(receiver);
+    let make_service = make_service_fn(move |_| {
+        let receiver = receiver.clone();
+        let sync_requests = sync_requests.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, receiver.clone(), sync_requests.clone())
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    Ok((server.local_addr(), server))
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    receiver: Arc<Receiver>,
+    sync_requests: Arc<Notify>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "only POST is supported".to_string(),
+        ));
+    }
+    let bytes = match read_body(request.into_body()).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            return Ok(response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "event too large".to_string(),
+            ))
+        }
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    let outcome = receiver.handle(&bytes);
+    match &outcome {
+        Outcome::Sync(file_key) => {
+            info!("{}  Webhook event for {}, syncing...", LINK, file_key);
+            sync_requests.notify_one();
+        }
+        Outcome::Ignored(reason) => debug!("{} Webhook event ignored: {}", INFO, reason),
+        Outcome::Unauthorized | Outcome::Invalid(_) => {
+            warn!("{} Webhook request rejected: {}", ERROR, outcome.message())
+        }
+    }
+    Ok(response(outcome.status(), outcome.message()))
+}
+
+/// Reads the body chunk by chunk, None as soon as it's bigger than `MAX_BODY_BYTES`.
+/// Chunked bodies don't tell their size up front, so the limit is checked while reading.
+async fn read_body(mut body: Body) -> hyper::Result<Option<Vec<u8>>> {
+    if body.size_hint().lower() > MAX_BODY_BYTES as u64 {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+fn response(status: StatusCode, message: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(message));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, passcode: &str) -> Vec<u8> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/webhooks")
+            .join(name);
+        std::fs::read_to_string(path)
+            .unwrap()
+            .replace("FILE_ID", "abc")
+            .replace("PASSCODE", passcode)
+            .into_bytes()
+    }
+
+    #[test]
+    fn only_updates_of_the_file_should_be_synced() {
+        let receiver = Receiver::new("secret", "abc").unwrap();
+
+        assert_eq!(
+            receiver.handle(&sample("file_update.json", "secret")),
+            Outcome::Sync("abc".to_string())
+        );
+        assert_eq!(
+            receiver.handle(&sample("library_publish.json", "secret")),
+            Outcome::Sync("abc".to_string())
+        );
+        assert_eq!(
+            receiver.handle(&sample("ping.json", "secret")),
+            Outcome::Ignored("ping".to_string())
+        );
+        assert!(matches!(
+            Receiver::new("secret", "other")
+                .unwrap()
+                .handle(&sample("file_update.json", "secret")),
+            Outcome::Ignored(_)
+        ));
+        assert_eq!(
+            Receiver::new("wrong", "abc")
+                .unwrap()
+                .handle(&sample("file_update.json", "secret")),
+            Outcome::Unauthorized
+        );
+        assert!(matches!(receiver.handle(b"{}"), Outcome::Invalid(_)));
+    }
+
+    #[test]
+    fn empty_passcodes_should_be_rejected() {
+        assert!(Receiver::new("", "abc").is_err());
+        assert!(Receiver::new("  ", "abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn chunked_events_over_the_limit_should_be_rejected() -> anyhow::Result<()> {
+        let (mut sender, body) = Body::channel();
+        // a chunked body, without content length
+        tokio::spawn(async move {
+            let chunk = vec![b' '; 64 * 1024];
+            // twice the limit
+            for _ in 0..32 {
+                if sender.send_data(chunk.clone().into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let request = Request::post("/").body(body)?;
+
+        let response = handle_request(
+            request,
+            Arc::new(Receiver::new("secret", "abc")?),
+            Arc::new(Notify::new()),
+        )
+        .await?;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn posted_events_should_request_a_sync() -> anyhow::Result<()> {
+        let sync_requests = Arc::new(Notify::new());
+        let (addr, server) = bind(
+            ([127, 0, 0, 1], 0).into(),
+            Receiver::new("secret", "abc")?,
+            sync_requests.clone(),
+        )?;
+        tokio::spawn(server);
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", addr);
+
+        let rejected = client
+            .post(&url)
+            .body(sample("file_update.json", "wrong"))
+            .send()
+            .await?;
+        assert_eq!(rejected.status(), StatusCode::UNAUTHORIZED);
+
+        let accepted = client
+            .post(&url)
+            .body(sample("file_update.json", "secret"))
+            .send()
+            .await?;
+        assert_eq!(accepted.status(), StatusCode::OK);
+        tokio::time::timeout(std::time::Duration::from_secs(5), sync_requests.notified()).await?;
+        Ok(())
+    }
+}